@@ -1,6 +1,9 @@
+use std::cell::RefCell;
 use std::sync::Arc;
 
 use crate::context::LockableContext;
+use crate::diagnostic::{Location, SourceMap};
+use crate::error::TracedError;
 
 use crate::nodes::*;
 use crate::rules::Rule;
@@ -10,34 +13,132 @@ use pest::iterators::Pair;
 
 pub trait Node: std::fmt::Display + std::fmt::Debug + DowncastSync {
     fn eval(&self, ctx: LockableContext) -> ValueResult;
+    fn span(&self) -> Option<&Span> {
+        None
+    }
 }
 
 impl_downcast!(sync Node);
 
+/// Byte offsets of a node inside of its script
+#[derive(Clone)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub source: Arc<SourceMap>,
+}
+
+impl Span {
+    pub fn location(&self) -> Location {
+        self.source.locate(self.start, self.end)
+    }
+}
+
+impl std::fmt::Debug for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Wraps every node created by `make_ast` and attaches its span to errors
+#[derive(Debug)]
+pub struct Spanned {
+    pub node: Arc<dyn Node>,
+    pub span: Span,
+}
+
+impl Node for Spanned {
+    fn eval(&self, ctx: LockableContext) -> ValueResult {
+        let step = ctx.read().budget.step();
+        step.and_then(|_| self.node.eval(ctx))
+            .map_err(|err| TracedError::trace(err, &self.span))
+    }
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
+impl std::fmt::Display for Spanned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.node)
+    }
+}
+
+thread_local! {
+    // the source of the program, which is currently being built
+    static SOURCE: RefCell<Option<Arc<SourceMap>>> = const { RefCell::new(None) };
+}
+
+/// Builds the tree of a whole program, every node will know its span inside of `source`
+pub fn make_program(
+    root: Pair<Rule>,
+    source: Arc<SourceMap>,
+) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+    let previous = SOURCE.with(|current| current.replace(Some(source)));
+    let result = make_ast(root);
+    SOURCE.with(|current| current.replace(previous));
+    result
+}
+
+/// The file `pair` was written in, if a program is being built
+pub fn source_file(pair: &Pair<Rule>) -> Option<Arc<str>> {
+    let start = pair.as_span().start();
+    SOURCE.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map(|source| source.locate(start, start).file)
+    })
+}
+
 pub fn make_ast(
     root: Pair<Rule>,
 ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+    let span = root.as_span();
+    let (start, end) = (span.start(), span.end());
+    let node = make_node(root)?;
+    match SOURCE.with(|current| current.borrow().clone()) {
+        Some(source) => Ok(Arc::new(Spanned {
+            node,
+            span: Span { start, end, source },
+        })),
+        None => Ok(node),
+    }
+}
+
+fn make_node(root: Pair<Rule>) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
     match root.as_rule() {
         Rule::Bool => Ok(Arc::new(BooleanLiteral {
             value: root.as_span().as_str().parse::<bool>()?,
         })),
+        Rule::None => Ok(Arc::new(NoneLiteral)),
         Rule::Int => IntegerLiteral::parse_str(root.as_span().as_str()),
         Rule::Float => FloatLiteral::parse_str(root.as_span().as_str()),
         Rule::Range => RangeLiteral::parse_inner(root.into_inner()),
         Rule::String => StringLiteral::parse_inner(root.into_inner()),
         Rule::List => ListLiteral::parse_inner(root.into_inner()),
         Rule::Dict => DictLiteral::parse_inner(root.into_inner()),
+        Rule::ListComprehension => Comprehension::parse_inner(root.into_inner(), false),
+        Rule::DictComprehension => Comprehension::parse_inner(root.into_inner(), true),
         Rule::Expr => Expr::parse_inner(root.into_inner()),
         Rule::Assignment => Assignment::parse_inner(root.into_inner()),
+        Rule::Destructuring => Destructuring::parse_inner(root.into_inner()),
+        Rule::Declaration => Declaration::parse_inner(root.into_inner()),
         Rule::Ident => Ident::parse(root),
         Rule::Block => Block::parse(root.into_inner(), false),
         Rule::RootBlock => Block::parse(root.into_inner().next().unwrap().into_inner(), true),
         Rule::Conditional => Conditional::parse(root.into_inner()),
         Rule::WhileLoop => While::parse(root.into_inner()),
+        Rule::Match => Match::parse_inner(root.into_inner()),
+        Rule::Try => Try::parse_inner(root.into_inner()),
         Rule::ForLoop => For::parse(root.into_inner()),
         Rule::Function => FunctionLiteral::parse_inner(root.into_inner()),
         Rule::Capture => Pipe::parse_inner(root.into_inner().next().unwrap().into_inner(), true),
         Rule::Pipe => Pipe::parse_inner(root.into_inner(), false),
+        Rule::Import => {
+            let file = source_file(&root);
+            Import::parse_inner(root.into_inner(), file)
+        }
         Rule::KeywordStatement => KeywordStatement::parse_inner(root.into_inner()),
         _ => {
             println!("{:?}", root);
@@ -0,0 +1,136 @@
+use crate::error::CashError;
+use parking_lot::{const_mutex, Mutex};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Stack size of every thread which evaluates scripts
+pub const STACK_SIZE: usize = 128 * 1024 * 1024;
+
+// a nested call uses less than 10 KiB of the stack in debug builds, the rest is left for deep expressions
+const STACK_PER_CALL: usize = 64 * 1024;
+
+/// Fits into `STACK_SIZE`, so that recursion fails with an error instead of overflowing the stack
+pub const DEFAULT_MAX_DEPTH: usize = STACK_SIZE / STACK_PER_CALL;
+
+// the clock is only read every few steps
+const STEPS_PER_CLOCK_CHECK: u64 = 64;
+
+/// Limits for a single execution, `None` means unlimited
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// evaluated nodes
+    pub max_steps: Option<u64>,
+    /// nested function calls
+    pub max_depth: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            timeout: None,
+        }
+    }
+}
+
+/// Tracks how much of its limits an execution has used up
+#[derive(Debug)]
+pub struct Budget {
+    pub limits: Limits,
+    steps: AtomicU64,
+    deadline: Mutex<Option<Instant>>,
+    interrupted: AtomicBool,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Budget {
+            limits,
+            steps: AtomicU64::new(0),
+            deadline: const_mutex(None),
+            interrupted: AtomicBool::new(false),
+        }
+    }
+
+    /// Resets the budget at the start of an execution
+    pub fn start(&self) {
+        self.steps.store(0, Ordering::Relaxed);
+        self.interrupted.store(false, Ordering::SeqCst);
+        *self.deadline.lock() = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// Called before a node is evaluated
+    pub fn step(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.check_interrupt()?;
+        let steps = self.steps.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(max_steps) = self.limits.max_steps {
+            if steps > max_steps {
+                return CashError::StepLimitExceeded(max_steps).boxed();
+            }
+        }
+        if steps % STEPS_PER_CLOCK_CHECK == 0 {
+            self.check_deadline()?;
+        }
+        Ok(())
+    }
+
+    /// Called while blocked on processes or other tasks, when no nodes are evaluated
+    pub fn check_waiting(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.check_interrupt()?;
+        self.check_deadline()
+    }
+
+    fn check_deadline(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(deadline) = *self.deadline.lock() {
+            if Instant::now() >= deadline {
+                let timeout = self.limits.timeout.unwrap_or_default();
+                return CashError::Timeout(timeout.as_millis() as u64).boxed();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+
+    pub fn check_interrupt(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.interrupted.load(Ordering::SeqCst) {
+            CashError::Interrupted.boxed()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Called before a function is entered
+    pub fn check_depth(&self, depth: usize) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.limits.max_depth {
+            Some(max_depth) if depth > max_depth => {
+                CashError::RecursionLimitExceeded(max_depth).boxed()
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Cancels the execution of a runtime from another thread
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    budget: Arc<Budget>,
+}
+
+impl InterruptHandle {
+    pub(crate) fn new(budget: Arc<Budget>) -> Self {
+        InterruptHandle { budget }
+    }
+
+    /// The running execution stops at the next node and kills spawned processes
+    /// Has no effect on executions started afterwards
+    pub fn interrupt(&self) {
+        self.budget.interrupt();
+    }
+}
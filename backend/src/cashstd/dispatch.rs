@@ -1,19 +1,44 @@
 use super::*;
 use crate::error::CashError;
 use crate::value::{Value, ValueResult};
-use crate::values::{BooleanValue, BuiltInFunction, DictValue, ListValue, NoneValue, StringValue};
-use std::env;
+use crate::values::{
+    BooleanValue, BuiltInFunction, DictValue, ErrorValue, ListValue, NoneValue, StringValue,
+};
 
 use crate::context::LockableContext;
 
-fn print_closure(params: Vec<Box<dyn Value>>, _ctx: LockableContext) -> ValueResult {
+fn print_closure(params: Vec<Box<dyn Value>>, ctx: LockableContext) -> ValueResult {
+    let mut text = String::new();
     for param in params {
-        print!("{}", param);
+        text += &param.to_string();
     }
-    println!();
+    text.push('\n');
+    ctx.read().output.write_out(&text)?;
     NoneValue::boxed()
 }
 
+fn eprint_closure(params: Vec<Box<dyn Value>>, ctx: LockableContext) -> ValueResult {
+    let mut text = String::new();
+    for param in params {
+        text += &param.to_string();
+    }
+    text.push('\n');
+    ctx.read().output.write_err(&text)?;
+    NoneValue::boxed()
+}
+
+/// `error(message, data)`, creates an error value, which may be thrown
+fn error_closure(mut params: Vec<Box<dyn Value>>, _ctx: LockableContext) -> ValueResult {
+    match params.len() {
+        1 => ErrorValue::boxed(params.remove(0).to_string(), NoneValue::boxed()?),
+        2 => {
+            let message = params.remove(0).to_string();
+            ErrorValue::boxed(message, params.remove(0))
+        }
+        _ => CashError::InvalidParameterCount(params.len(), 2).boxed(),
+    }
+}
+
 fn type_closure(mut params: Vec<Box<dyn Value>>, _ctx: LockableContext) -> ValueResult {
     if params.len() == 1 {
         StringValue::boxed(params.remove(0).get_type_name().to_owned())
@@ -42,11 +67,11 @@ fn exists_closure(mut params: Vec<Box<dyn Value>>, ctx: LockableContext) -> Valu
     }
 }
 
-fn cwd_closure(params: Vec<Box<dyn Value>>, _ctx: LockableContext) -> ValueResult {
+fn cwd_closure(params: Vec<Box<dyn Value>>, ctx: LockableContext) -> ValueResult {
     if params.is_empty() {
         StringValue::boxed(
-            env::current_dir()
-                .expect("Could not get current working directory")
+            ctx.read()
+                .get_cwd()
                 .to_str()
                 .expect("Could not convert Pathbuf to String")
                 .to_owned(),
@@ -56,9 +81,16 @@ fn cwd_closure(params: Vec<Box<dyn Value>>, _ctx: LockableContext) -> ValueResul
     }
 }
 
-fn cd_closure(mut params: Vec<Box<dyn Value>>, _ctx: LockableContext) -> ValueResult {
+fn cd_closure(mut params: Vec<Box<dyn Value>>, ctx: LockableContext) -> ValueResult {
     if params.len() == 1 {
-        env::set_current_dir(std::path::Path::new(&params.remove(0).to_string()))?;
+        let path = ctx.read().resolve_path(params.remove(0).to_string());
+        ctx.read().permissions.check_path(&path)?;
+        let path = std::fs::canonicalize(path)?;
+        if !path.is_dir() {
+            return CashError::InvalidArguments(path.display().to_string(), "directory".to_owned())
+                .boxed();
+        }
+        *ctx.read().cwd.write() = path;
         NoneValue::boxed()
     } else {
         CashError::InvalidParameterCount(params.len(), 1).boxed()
@@ -71,11 +103,13 @@ pub fn get_stdlib_function(ident: &str) -> Option<Box<dyn Value>> {
         "cwd" => BuiltInFunction::boxed(&cwd_closure),
 
         "print" => BuiltInFunction::boxed(&print_closure),
+        "eprint" => BuiltInFunction::boxed(&eprint_closure),
         "each" => BuiltInFunction::boxed(&each_closure),
         "map" => BuiltInFunction::boxed(&map_closure),
         "filter" => BuiltInFunction::boxed(&filter_closure),
         "reduce" => BuiltInFunction::boxed(&reduce_closure),
         "join" => BuiltInFunction::boxed(&join_closure),
+        "format" => BuiltInFunction::boxed(&format_closure),
         "len" => BuiltInFunction::boxed(&len_closure),
 
         "remove" => BuiltInFunction::boxed(&remove_closure),
@@ -85,6 +119,7 @@ pub fn get_stdlib_function(ident: &str) -> Option<Box<dyn Value>> {
 
         "type" => BuiltInFunction::boxed(&type_closure),
         "exists" => BuiltInFunction::boxed(&exists_closure),
+        "error" => BuiltInFunction::boxed(&error_closure),
 
         //Math
         "sqrt" => BuiltInFunction::boxed(&sqrt_closure),
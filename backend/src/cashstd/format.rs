@@ -0,0 +1,255 @@
+use crate::context::LockableContext;
+use crate::error::CashError;
+use crate::value::{Value, ValueResult};
+use crate::values::{FloatValue, IntegerValue, StringValue};
+
+/// `[[fill]align][sign][#][0][width][,|_][.precision][type]`, e.g. `>8`, `+.2f`, `#010x` or `,d`
+/// Used by `${value:spec}` and `format()`
+#[derive(Debug, Clone, Default)]
+pub struct FormatSpec {
+    pub fill: Option<char>,
+    /// `<`, `>` or `^`, numbers are aligned right and other values left by default
+    pub align: Option<char>,
+    /// `+` shows the sign of positive numbers too, ` ` puts a space there
+    pub sign: Option<char>,
+    /// `#` prefixes hexadecimal, octal and binary numbers with `0x`, `0o` and `0b`
+    pub alternate: bool,
+    /// `0` pads numbers with zeros after the sign
+    pub zero: bool,
+    pub width: usize,
+    /// `,` or `_` between groups of digits
+    pub grouping: Option<char>,
+    /// digits after the decimal point, or the maximum length of other values
+    pub precision: Option<usize>,
+    /// `d`, `x`, `X`, `o`, `b`, `f`, `e` or `s`
+    pub kind: Option<char>,
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let chars: Vec<char> = spec.chars().collect();
+        let is_align = |c: Option<&char>| matches!(c, Some('<' | '>' | '^'));
+        let mut format = FormatSpec::default();
+        let mut i = 0;
+        if is_align(chars.get(1)) {
+            format.fill = Some(chars[0]);
+            format.align = Some(chars[1]);
+            i = 2;
+        } else if is_align(chars.first()) {
+            format.align = Some(chars[0]);
+            i = 1;
+        }
+        if let Some(sign @ ('+' | '-' | ' ')) = chars.get(i) {
+            format.sign = Some(*sign);
+            i += 1;
+        }
+        if chars.get(i) == Some(&'#') {
+            format.alternate = true;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'0') {
+            format.zero = true;
+            i += 1;
+        }
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while chars.get(*i).is_some_and(char::is_ascii_digit) {
+                *i += 1;
+            }
+            chars[start..*i]
+                .iter()
+                .collect::<String>()
+                .parse::<usize>()
+                .ok()
+        };
+        format.width = digits(&mut i).unwrap_or(0);
+        if let Some(grouping @ (',' | '_')) = chars.get(i) {
+            format.grouping = Some(*grouping);
+            i += 1;
+        }
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            format.precision = match digits(&mut i) {
+                Some(precision) => Some(precision),
+                None => return CashError::InvalidFormat(spec.to_owned()).boxed(),
+            };
+        }
+        if let Some(kind @ ('d' | 'x' | 'X' | 'o' | 'b' | 'f' | 'e' | 's')) = chars.get(i) {
+            format.kind = Some(*kind);
+            i += 1;
+        }
+        if i != chars.len() {
+            return CashError::InvalidFormat(spec.to_owned()).boxed();
+        }
+        Ok(format)
+    }
+
+    pub fn apply(
+        &self,
+        value: &dyn Value,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let integer = value
+            .downcast_ref::<IntegerValue>()
+            .map(|value| value.value);
+        let float = value.downcast_ref::<FloatValue>().map(|value| value.value);
+        let (negative, prefix, digits) = match (integer, float, self.kind) {
+            (Some(value), _, None | Some('d')) if self.precision.is_none() => (
+                value < 0,
+                "",
+                self.group(value.unsigned_abs().to_string(), 3),
+            ),
+            (Some(value), _, Some(kind @ ('x' | 'X' | 'o' | 'b'))) => {
+                let absolute = value.unsigned_abs();
+                let (digits, prefix) = match kind {
+                    'x' => (format!("{:x}", absolute), "0x"),
+                    'X' => (format!("{:X}", absolute), "0x"),
+                    'o' => (format!("{:o}", absolute), "0o"),
+                    _ => (format!("{:b}", absolute), "0b"),
+                };
+                let prefix = if self.alternate { prefix } else { "" };
+                (value < 0, prefix, self.group(digits, 4))
+            }
+            (Some(_), _, _) | (_, Some(_), _) if !matches!(self.kind, Some('s')) => {
+                let value = float.unwrap_or_else(|| integer.unwrap_or(0) as f64);
+                if matches!(self.kind, Some('d' | 'x' | 'X' | 'o' | 'b')) {
+                    return CashError::InvalidType("float".to_owned(), "format".to_owned()).boxed();
+                }
+                let absolute = value.abs();
+                let text = match (self.kind, self.precision) {
+                    (Some('e'), precision) => format!("{:.*e}", precision.unwrap_or(6), absolute),
+                    (Some('f'), precision) => format!("{:.*}", precision.unwrap_or(6), absolute),
+                    (_, Some(precision)) => format!("{:.*}", precision, absolute),
+                    _ => absolute.to_string(),
+                };
+                let text = match text.find(|c: char| !c.is_ascii_digit()) {
+                    Some(end) => self.group(text[..end].to_owned(), 3) + &text[end..],
+                    None => self.group(text, 3),
+                };
+                (value.is_sign_negative(), "", text)
+            }
+            _ => {
+                if self.sign.is_some() || self.alternate || self.zero || self.grouping.is_some() {
+                    return CashError::InvalidType(
+                        value.get_type_name().to_owned(),
+                        "numeric format".to_owned(),
+                    )
+                    .boxed();
+                }
+                let text = value.to_string();
+                let text = match self.precision {
+                    Some(precision) => text.chars().take(precision).collect(),
+                    None => text,
+                };
+                return Ok(self.pad(String::new(), text, '<'));
+            }
+        };
+        let sign = match (negative, self.sign) {
+            (true, _) => "-",
+            (false, Some('+')) => "+",
+            (false, Some(' ')) => " ",
+            _ => "",
+        };
+        Ok(self.pad(sign.to_owned() + prefix, digits, '>'))
+    }
+
+    /// Inserts the grouping character between every `size` digits, counted from the right
+    fn group(&self, digits: String, size: usize) -> String {
+        let grouping = match self.grouping {
+            Some(grouping) => grouping,
+            None => return digits,
+        };
+        let chars: Vec<char> = digits.chars().collect();
+        let mut grouped = String::new();
+        for (i, c) in chars.iter().enumerate() {
+            if i > 0 && (chars.len() - i) % size == 0 {
+                grouped.push(grouping);
+            }
+            grouped.push(*c);
+        }
+        grouped
+    }
+
+    /// Zeros are put between `sign` and `text`
+    fn pad(&self, sign: String, text: String, default_align: char) -> String {
+        let length = sign.chars().count() + text.chars().count();
+        if length >= self.width {
+            return sign + &text;
+        }
+        let missing = self.width - length;
+        if self.zero && self.align.is_none() {
+            return sign + &"0".repeat(missing) + &text;
+        }
+        let fill = self.fill.unwrap_or(' ');
+        let (left, right) = match self.align.unwrap_or(default_align) {
+            '<' => (0, missing),
+            '^' => (missing / 2, missing - missing / 2),
+            _ => (missing, 0),
+        };
+        let repeat = |count: usize| std::iter::repeat(fill).take(count).collect::<String>();
+        repeat(left) + &sign + &text + &repeat(right)
+    }
+}
+
+/// `format(template, ...args)`, replaces `{}`, `{index}` and `{index:spec}` with the arguments
+/// `{{` and `}}` are literal braces
+pub fn format_closure(mut params: Vec<Box<dyn Value>>, _ctx: LockableContext) -> ValueResult {
+    if params.is_empty() {
+        return CashError::InvalidParameterCount(0, 1).boxed();
+    }
+    let template = params.remove(0).to_string();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    let mut next = 0;
+    // one more than the highest index of a placeholder
+    let mut used = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return CashError::InvalidFormat(template).boxed(),
+                    }
+                }
+                let (index, spec) = match placeholder.split_once(':') {
+                    Some((index, spec)) => (index, spec),
+                    None => (placeholder.as_str(), ""),
+                };
+                let index = if index.is_empty() {
+                    next += 1;
+                    next - 1
+                } else {
+                    match index.trim().parse::<usize>() {
+                        Ok(index) => index,
+                        Err(_) => return CashError::InvalidFormat(placeholder).boxed(),
+                    }
+                };
+                let value = match params.get(index) {
+                    Some(value) => value,
+                    None => {
+                        return CashError::InvalidParameterCount(params.len() + 1, index + 2)
+                            .boxed()
+                    }
+                };
+                text += &FormatSpec::parse(spec)?.apply(&**value)?;
+                used = used.max(index + 1);
+            }
+            '}' => return CashError::InvalidFormat(template).boxed(),
+            c => text.push(c),
+        }
+    }
+    if used < params.len() {
+        return CashError::InvalidParameterCount(params.len() + 1, used + 1).boxed();
+    }
+    StringValue::boxed(text)
+}
@@ -1,11 +1,22 @@
 use crate::context::LockableContext;
 use crate::error::CashError;
 use crate::value::{Value, ValueResult};
-use crate::values::{BooleanValue, DictValue, IntegerValue, ListValue, NoneValue, StringValue};
+use crate::values::{
+    BooleanValue, DictValue, IntegerValue, IteratorValue, ListValue, NoneValue, RangeValue,
+    StringValue,
+};
 use std::collections::HashMap;
 
+/// Iterators and open ranges may be infinite, so they are mapped and filtered lazily
+fn is_lazy(value: &dyn Value) -> bool {
+    match value.downcast_ref::<RangeValue>() {
+        Some(range) => range.upper.is_none(),
+        None => value.get_type_name() == "iterator",
+    }
+}
+
 /// Takes exactly 2 params
-/// first: a list/dict to be mapped
+/// first: a list/dict to be mapped, other iterables return a list, or an iterator if they may be infinite
 /// second: the function, which will receive either the value and index of the list item, or the key and value of the dict item
 pub fn map_closure(mut params: Vec<Box<dyn Value>>, ctx: LockableContext) -> ValueResult {
     if params.len() == 2 {
@@ -33,7 +44,16 @@ pub fn map_closure(mut params: Vec<Box<dyn Value>>, ctx: LockableContext) -> Val
                 DictValue::boxed(dict)
             }
             _ => {
-                CashError::InvalidArguments(type_name.to_owned(), "List or Dict".to_owned()).boxed()
+                let lazy = is_lazy(&*first);
+                let mapped = first.iter()?.enumerate().map(move |(i, value)| {
+                    let index = Box::new(IntegerValue { value: i as i64 });
+                    second.call(vec![value?, index], ctx.clone())
+                });
+                if lazy {
+                    IteratorValue::boxed(mapped)
+                } else {
+                    ListValue::boxed(mapped.collect::<Result<_, _>>()?)
+                }
             }
         }
     } else {
@@ -42,12 +62,17 @@ pub fn map_closure(mut params: Vec<Box<dyn Value>>, ctx: LockableContext) -> Val
 }
 
 pub fn each_closure(params: Vec<Box<dyn Value>>, ctx: LockableContext) -> ValueResult {
-    map_closure(params, ctx)?;
+    let mapped = map_closure(params, ctx)?;
+    if let Ok(items) = mapped.downcast::<IteratorValue>() {
+        for item in *items {
+            item?;
+        }
+    }
     NoneValue::boxed()
 }
 
 /// Takes exactly 2 params
-/// first: a list/dict to be filtered
+/// first: a list/dict to be filtered, other iterables return a list, or an iterator if they may be infinite
 /// second: the function, which will receive either the value and index of the list item, or the key and value of the dict item
 pub fn filter_closure(mut params: Vec<Box<dyn Value>>, ctx: LockableContext) -> ValueResult {
     if params.len() == 2 {
@@ -93,7 +118,31 @@ pub fn filter_closure(mut params: Vec<Box<dyn Value>>, ctx: LockableContext) ->
                 DictValue::boxed(dict)
             }
             _ => {
-                CashError::InvalidArguments(type_name.to_owned(), "List or Dict".to_owned()).boxed()
+                let lazy = is_lazy(&*first);
+                let filtered = first.iter()?.enumerate().filter_map(move |(i, value)| {
+                    let keep = value.and_then(|value| {
+                        let index = Box::new(IntegerValue { value: i as i64 });
+                        let res = second.call(vec![value.clone(), index], ctx.clone())?;
+                        match res.downcast_ref::<BooleanValue>() {
+                            Some(boolean) => Ok((boolean.value, value)),
+                            None => CashError::InvalidType(
+                                res.get_type_name().to_owned(),
+                                "Boolean".to_owned(),
+                            )
+                            .boxed(),
+                        }
+                    });
+                    match keep {
+                        Ok((true, value)) => Some(Ok(value)),
+                        Ok((false, _)) => None,
+                        Err(err) => Some(Err(err)),
+                    }
+                });
+                if lazy {
+                    IteratorValue::boxed(filtered)
+                } else {
+                    ListValue::boxed(filtered.collect::<Result<_, _>>()?)
+                }
             }
         }
     } else {
@@ -106,16 +155,11 @@ pub fn reduce_closure(mut params: Vec<Box<dyn Value>>, ctx: LockableContext) ->
         let first = params.remove(0);
         let second = params.remove(0);
         let mut third = params.remove(0);
-        let type_name = first.get_type_name();
-        if let Ok(first) = first.downcast::<ListValue>() {
-            for (i, value) in first.values.into_iter().enumerate() {
-                let index = Box::new(IntegerValue { value: i as i64 });
-                third = second.call(vec![third, value, index], ctx.clone())?;
-            }
-            Ok(third)
-        } else {
-            CashError::InvalidArguments(type_name.to_owned(), "List".to_owned()).boxed()
+        for (i, value) in first.iter()?.enumerate() {
+            let index = Box::new(IntegerValue { value: i as i64 });
+            third = second.call(vec![third, value?, index], ctx.clone())?;
         }
+        Ok(third)
     } else {
         CashError::InvalidParameterCount(params.len(), 4).boxed()
     }
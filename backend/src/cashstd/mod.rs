@@ -1,7 +1,9 @@
 mod dispatch;
+mod format;
 mod list;
 #[macro_use]
 mod math;
 pub use dispatch::*;
+pub use format::*;
 pub use list::*;
 pub use math::*;
@@ -1,19 +1,43 @@
 use parking_lot::{const_mutex, const_rwlock, Mutex, RwLock};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use crate::budget::Budget;
 use crate::cashstd;
+use crate::error::CashError;
 use crate::executor::Executor;
+use crate::modules::Modules;
+use crate::output::Output;
+use crate::permissions::Permissions;
 use crate::value::Value;
-use crate::values::StringValue;
+use crate::values::{FloatValue, StringValue, Yielder};
 
 pub type LockableContext = Arc<RwLock<Context>>;
+pub type FunctionRegistry = Arc<RwLock<HashMap<String, Box<dyn Value>>>>;
 
 #[derive(Debug)]
 pub struct Context {
     parent: Option<LockableContext>,
     vars: HashMap<String, Box<dyn Value>>,
+    /// variables of this context declared with `const`
+    constants: HashSet<String>,
     pub executor: Arc<Mutex<Executor>>,
+    pub functions: FunctionRegistry,
+    pub permissions: Arc<Permissions>,
+    pub budget: Arc<Budget>,
+    pub modules: Arc<RwLock<Modules>>,
+    pub output: Output,
+    /// working directory of the runtime, the process cwd is never changed
+    pub cwd: Arc<RwLock<PathBuf>>,
+    /// amount of function calls leading to this context
+    pub depth: usize,
+    /// set inside of the body of a generator function
+    pub generator: Option<Arc<Yielder>>,
+    /// variables have to be declared with `let` or `const` before they are assigned
+    pub strict: bool,
+    /// modules which are evaluated by the imports leading to this context, in import order
+    pub imports: Arc<Vec<PathBuf>>,
 }
 
 impl Context {
@@ -21,24 +45,102 @@ impl Context {
         Context {
             parent: None,
             vars: HashMap::new(),
+            constants: HashSet::new(),
             executor: Arc::new(const_mutex(Executor::default())),
+            functions: Arc::new(const_rwlock(HashMap::new())),
+            permissions: Arc::new(Permissions::default()),
+            budget: Arc::new(Budget::new(Default::default())),
+            modules: Arc::new(const_rwlock(Modules::default())),
+            output: Output::default(),
+            cwd: Arc::new(const_rwlock(std::env::current_dir().unwrap_or_default())),
+            depth: 0,
+            generator: None,
+            strict: false,
+            imports: Arc::new(Vec::new()),
         }
     }
+
+    /// A context without access to the variables of `self`, but sharing the runtime state (executor, permissions, ...)
+    /// Its parent only contains the constants
+    pub fn isolated(&self) -> LockableContext {
+        let mut prelude = Context {
+            parent: None,
+            vars: HashMap::new(),
+            constants: HashSet::new(),
+            executor: self.get_executor(),
+            functions: self.functions.clone(),
+            permissions: self.permissions.clone(),
+            budget: self.budget.clone(),
+            modules: self.modules.clone(),
+            output: self.output.clone(),
+            cwd: self.cwd.clone(),
+            depth: self.depth,
+            generator: None,
+            strict: self.strict,
+            imports: self.imports.clone(),
+        };
+        prelude.set_constants();
+        Context::from_parent(Arc::new(const_rwlock(prelude)))
+    }
+
+    /// Defines `PI` and `E`
+    pub fn set_constants(&mut self) {
+        self.set_self(
+            "PI",
+            Box::new(FloatValue {
+                value: std::f64::consts::PI,
+            }),
+        );
+        self.set_self(
+            "E",
+            Box::new(FloatValue {
+                value: std::f64::consts::E,
+            }),
+        );
+    }
     pub fn from_parent(parent: LockableContext) -> LockableContext {
-        let executor = parent.read().get_executor();
+        let lock = parent.read();
+        let ctx = Context {
+            parent: None,
+            vars: HashMap::new(),
+            constants: HashSet::new(),
+            executor: lock.get_executor(),
+            functions: lock.functions.clone(),
+            permissions: lock.permissions.clone(),
+            budget: lock.budget.clone(),
+            modules: lock.modules.clone(),
+            output: lock.output.clone(),
+            cwd: lock.cwd.clone(),
+            depth: lock.depth,
+            generator: lock.generator.clone(),
+            strict: lock.strict,
+            imports: lock.imports.clone(),
+        };
+        drop(lock);
         Arc::new(const_rwlock(Context {
             parent: Some(parent),
-            vars: HashMap::new(),
-            executor,
+            ..ctx
         }))
     }
 
+    pub fn get_cwd(&self) -> PathBuf {
+        self.cwd.read().clone()
+    }
+
+    /// Resolves relative paths against the working directory of the runtime
+    pub fn resolve_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.cwd.read().join(path)
+    }
+
     pub fn get_executor(&self) -> Arc<Mutex<Executor>> {
         self.executor.clone()
     }
 
     pub fn get(&self, key: &str) -> Option<Box<dyn Value>> {
         if key.starts_with('$') {
+            if !self.permissions.env_read {
+                return None;
+            }
             if let Ok(val) = std::env::var(&key[1..]) {
                 return Some(Box::new(StringValue { value: val }));
             } else {
@@ -54,26 +156,45 @@ impl Context {
             }
         }
         // None
+        self.get_builtin(key)
+    }
+
+    /// Functions registered by the host take precedence over the stdlib
+    fn get_builtin(&self, key: &str) -> Option<Box<dyn Value>> {
+        if let Some(function) = self.functions.read().get(key) {
+            return Some((*function).clone());
+        }
         cashstd::get_stdlib_function(key)
     }
 
-    pub fn set(&mut self, key: &str, value: Box<dyn Value>) {
+    /// Fails if environment variables may not be written
+    pub fn set(
+        &mut self,
+        key: &str,
+        value: Box<dyn Value>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         if key.starts_with('$') {
+            self.permissions.check_env_write(key)?;
             std::env::set_var(&key[1..], &value.to_string());
-            return;
+            return Ok(());
+        }
+        // a variable declared in this context shadows the ones of its parents
+        if self.vars.contains_key(key) {
+            self.set_self(key, value);
+            return Ok(());
         }
         if let Some(parent) = &self.parent {
             if parent.read().get(key).is_some() {
-                parent.write().set(key, value);
-                return;
+                return parent.write().set(key, value);
             }
         }
         self.set_self(key, value);
+        Ok(())
     }
 
     pub fn exists(&self, key: &str) -> bool {
         if key.starts_with('$') {
-            return std::env::var(&key[1..]).is_ok();
+            return self.permissions.env_read && std::env::var(&key[1..]).is_ok();
         }
         if self.vars.contains_key(key) {
             return true;
@@ -84,12 +205,70 @@ impl Context {
             }
         }
         // None
-        cashstd::get_stdlib_function(key).is_some()
+        self.get_builtin(key).is_some()
     }
 
     pub fn set_self(&mut self, key: &str, value: Box<dyn Value>) {
         self.vars.insert(key.to_owned(), value);
     }
+
+    /// Binds in this context, environment variables are not scoped and written like in `set`
+    pub fn set_local(
+        &mut self,
+        key: &str,
+        value: Box<dyn Value>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if key.starts_with('$') {
+            return self.set(key, value);
+        }
+        self.set_self(key, value);
+        Ok(())
+    }
+
+    /// `let` and `const`, always binds in this context
+    /// Fails if a constant of the same name was declared in this context
+    pub fn declare(
+        &mut self,
+        key: &str,
+        value: Box<dyn Value>,
+        constant: bool,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.constants.contains(key) {
+            return CashError::ConstantAssignment(key.to_owned()).boxed();
+        }
+        if constant {
+            self.constants.insert(key.to_owned());
+        }
+        self.set_self(key, value);
+        Ok(())
+    }
+
+    /// Fails if `set` would overwrite a constant, or in strict mode if the variable was not declared
+    pub fn check_assign(&self, key: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if key.starts_with('$') {
+            return Ok(());
+        }
+        if self.vars.contains_key(key) {
+            if self.constants.contains(key) {
+                return CashError::ConstantAssignment(key.to_owned()).boxed();
+            }
+            return Ok(());
+        }
+        match &self.parent {
+            Some(parent) => parent.read().check_assign(key),
+            None if self.strict => CashError::UndeclaredVariable(key.to_owned()).boxed(),
+            None => Ok(()),
+        }
+    }
+
+    /// Only looks at the variables of this context, ignoring parents and builtins
+    pub fn get_self(&self, key: &str) -> Option<Box<dyn Value>> {
+        self.vars.get(key).map(|value| (*value).clone())
+    }
+
+    pub fn vars(&self) -> impl Iterator<Item = (&String, &Box<dyn Value>)> {
+        self.vars.iter()
+    }
 }
 
 impl std::fmt::Display for Context {
@@ -0,0 +1,318 @@
+use crate::error::CashError;
+use crate::value::{Value, ValueResult};
+use crate::values::{
+    BooleanValue, BuiltInFunction, DictValue, FloatValue, IntegerValue, ListValue, NoneValue,
+    StringValue,
+};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Converts a Rust value into a cash value
+pub trait IntoValue {
+    fn into_value(self) -> Box<dyn Value>;
+}
+
+/// Converts a cash value into a Rust value
+/// Fails with `CashError::InvalidArguments` if the cash value has the wrong type
+pub trait FromValue: Sized {
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>>;
+    /// Name of the expected cash type, used in error messages
+    fn expected() -> String;
+}
+
+fn invalid<T: FromValue>(value: &dyn Value) -> Result<T, Box<dyn Error + Send + Sync>> {
+    CashError::InvalidArguments(value.get_type_name().to_owned(), T::expected()).boxed()
+}
+
+impl IntoValue for Box<dyn Value> {
+    fn into_value(self) -> Box<dyn Value> {
+        self
+    }
+}
+
+impl FromValue for Box<dyn Value> {
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(value)
+    }
+    fn expected() -> String {
+        "any".to_owned()
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Box<dyn Value> {
+        Box::new(NoneValue)
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if value.downcast_ref::<NoneValue>().is_some() {
+            Ok(())
+        } else {
+            invalid(&*value)
+        }
+    }
+    fn expected() -> String {
+        "none".to_owned()
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Box<dyn Value> {
+        Box::new(IntegerValue { value: self })
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        match value.downcast_ref::<IntegerValue>() {
+            Some(integer) => Ok(integer.value),
+            None => invalid(&*value),
+        }
+    }
+    fn expected() -> String {
+        "integer".to_owned()
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Box<dyn Value> {
+        Box::new(FloatValue { value: self })
+    }
+}
+
+impl FromValue for f64 {
+    // integers are accepted as well, just like in the math functions
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if let Some(float) = value.downcast_ref::<FloatValue>() {
+            Ok(float.value)
+        } else if let Some(integer) = value.downcast_ref::<IntegerValue>() {
+            Ok(integer.value as f64)
+        } else {
+            invalid(&*value)
+        }
+    }
+    fn expected() -> String {
+        "float".to_owned()
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Box<dyn Value> {
+        Box::new(BooleanValue { value: self })
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        match value.downcast_ref::<BooleanValue>() {
+            Some(boolean) => Ok(boolean.value),
+            None => invalid(&*value),
+        }
+    }
+    fn expected() -> String {
+        "boolean".to_owned()
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Box<dyn Value> {
+        Box::new(StringValue { value: self })
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Box<dyn Value> {
+        Box::new(StringValue {
+            value: self.to_owned(),
+        })
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        match value.downcast::<StringValue>() {
+            Ok(string) => Ok(string.value),
+            Err(value) => invalid(&*value),
+        }
+    }
+    fn expected() -> String {
+        "string".to_owned()
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Box<dyn Value> {
+        Box::new(ListValue {
+            values: self.into_iter().map(IntoValue::into_value).collect(),
+        })
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        match value.downcast::<ListValue>() {
+            Ok(list) => list.values.into_iter().map(T::from_value).collect(),
+            Err(value) => invalid(&*value),
+        }
+    }
+    fn expected() -> String {
+        format!("list of {}", T::expected())
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Box<dyn Value> {
+        Box::new(DictValue {
+            values: self
+                .into_iter()
+                .map(|(key, value)| (key, value.into_value()))
+                .collect(),
+        })
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        match value.downcast::<DictValue>() {
+            Ok(dict) => dict
+                .values
+                .into_iter()
+                .map(|(key, value)| Ok((key, T::from_value(value)?)))
+                .collect(),
+            Err(value) => invalid(&*value),
+        }
+    }
+    fn expected() -> String {
+        format!("dict of {}", T::expected())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Box<dyn Value> {
+        match self {
+            Some(value) => value.into_value(),
+            None => Box::new(NoneValue),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if value.downcast_ref::<NoneValue>().is_some() {
+            Ok(None)
+        } else {
+            Ok(Some(T::from_value(value)?))
+        }
+    }
+    fn expected() -> String {
+        format!("{} or none", T::expected())
+    }
+}
+
+// tuples are represented as lists of a fixed length
+macro_rules! impl_tuple {
+    ($count:expr; $($name:ident $var:ident),+) => {
+        impl<$($name: IntoValue),+> IntoValue for ($($name,)+) {
+            fn into_value(self) -> Box<dyn Value> {
+                let ($($var,)+) = self;
+                Box::new(ListValue {
+                    values: vec![$($var.into_value()),+],
+                })
+            }
+        }
+
+        impl<$($name: FromValue),+> FromValue for ($($name,)+) {
+            fn from_value(value: Box<dyn Value>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+                match value.downcast::<ListValue>() {
+                    Ok(list) if list.values.len() == $count => {
+                        let mut values = list.values.into_iter();
+                        Ok(($(<$name as FromValue>::from_value(
+                            values.next().expect("length was checked"),
+                        )?,)+))
+                    }
+                    Ok(list) => {
+                        CashError::InvalidLength(list.values.len() as i64, Self::expected()).boxed()
+                    }
+                    Err(value) => invalid(&*value),
+                }
+            }
+            fn expected() -> String {
+                let types: Vec<String> = vec![$(<$name as FromValue>::expected()),+];
+                format!("list of ({})", types.join(", "))
+            }
+        }
+    };
+}
+
+impl_tuple!(1; A a);
+impl_tuple!(2; A a, B b);
+impl_tuple!(3; A a, B b, C c);
+impl_tuple!(4; A a, B b, C c, D d);
+impl_tuple!(5; A a, B b, C c, D d, E e);
+impl_tuple!(6; A a, B b, C c, D d, E e, F f);
+
+/// Return types of typed functions
+/// Either a plain value or a `Result`, whose error is passed on to the script
+pub trait IntoValueResult {
+    fn into_value_result(self) -> ValueResult;
+}
+
+impl<T: IntoValue> IntoValueResult for T {
+    fn into_value_result(self) -> ValueResult {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue, E: Into<Box<dyn Error + Send + Sync>>> IntoValueResult for Result<T, E> {
+    fn into_value_result(self) -> ValueResult {
+        match self {
+            Ok(value) => Ok(value.into_value()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// A Rust function with typed parameters, which can be called from cash
+/// `Args` is the tuple of parameter types and only exists to tell the impls apart
+pub trait TypedFunction<Args>: Send + Sync + 'static {
+    fn call_typed(&self, params: Vec<Box<dyn Value>>) -> ValueResult;
+}
+
+macro_rules! impl_typed_function {
+    ($count:expr; $($name:ident $var:ident),*) => {
+        impl<Func, Ret, $($name),*> TypedFunction<($($name,)*)> for Func
+        where
+            Func: Fn($($name),*) -> Ret + Send + Sync + 'static,
+            Ret: IntoValueResult,
+            $($name: FromValue,)*
+        {
+            fn call_typed(&self, params: Vec<Box<dyn Value>>) -> ValueResult {
+                if params.len() != $count {
+                    return CashError::InvalidParameterCount(params.len(), $count).boxed();
+                }
+                #[allow(unused_mut, unused_variables)]
+                let mut params = params.into_iter();
+                $(let $var = <$name as FromValue>::from_value(
+                    params.next().expect("length was checked"),
+                )?;)*
+                (self)($($var),*).into_value_result()
+            }
+        }
+    };
+}
+
+impl_typed_function!(0;);
+impl_typed_function!(1; A a);
+impl_typed_function!(2; A a, B b);
+impl_typed_function!(3; A a, B b, C c);
+impl_typed_function!(4; A a, B b, C c, D d);
+impl_typed_function!(5; A a, B b, C c, D d, E e);
+impl_typed_function!(6; A a, B b, C c, D d, E e, F f);
+
+/// Turns a typed Rust function, e.g. `fn(i64, String) -> Vec<String>`, into a callable value
+/// Arity and type errors are generated from the signature
+pub fn typed_function<Args, F: TypedFunction<Args>>(function: F) -> Box<dyn Value> {
+    BuiltInFunction::from_closure(move |params, _ctx| function.call_typed(params))
+}
@@ -0,0 +1,176 @@
+use crate::executor::Running;
+use crate::output::Output;
+use parking_lot::{const_mutex, Condvar, Mutex};
+use std::sync::{Arc, Once, Weak};
+use std::thread;
+use std::time::Duration;
+
+pub type DeadlockHandler = dyn Fn(&DeadlockReport) + Send + Sync;
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+static START: Once = Once::new();
+static INTERVAL: Mutex<Duration> = const_mutex(DEFAULT_INTERVAL);
+static INTERVAL_CHANGED: Condvar = Condvar::new();
+static LISTENERS: Mutex<Vec<Weak<Listener>>> = const_mutex(Vec::new());
+
+#[derive(Debug, Clone)]
+pub struct DeadlockedThread {
+    pub thread_id: usize,
+    pub backtrace: String,
+}
+
+/// Deadlocks found by a single check, every deadlock is a cycle of threads
+/// A deadlock is only reported once
+#[derive(Debug, Clone)]
+pub struct DeadlockReport {
+    pub deadlocks: Vec<Vec<DeadlockedThread>>,
+}
+
+impl std::fmt::Display for DeadlockReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} deadlocks detected", self.deadlocks.len())?;
+        for (i, threads) in self.deadlocks.iter().enumerate() {
+            write!(f, "\nDeadlock #{}", i)?;
+            for thread in threads {
+                write!(
+                    f,
+                    "\nThread Id {:#?}\n{}",
+                    thread.thread_id, thread.backtrace
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a runtime is told about deadlocks
+#[derive(Clone)]
+pub struct DeadlockDetection {
+    pub enabled: bool,
+    /// receives the reports instead of the error output of the runtime
+    pub handler: Option<Arc<DeadlockHandler>>,
+    /// `await`s of the runtime fail with `CashError::Deadlock`, if their job is deadlocked
+    pub fail_awaits: bool,
+}
+
+impl Default for DeadlockDetection {
+    fn default() -> Self {
+        DeadlockDetection {
+            enabled: true,
+            handler: None,
+            fail_awaits: false,
+        }
+    }
+}
+
+impl std::fmt::Debug for DeadlockDetection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeadlockDetection")
+            .field("enabled", &self.enabled)
+            .field("handler", &self.handler.as_ref().map(|_| "handler"))
+            .field("fail_awaits", &self.fail_awaits)
+            .finish()
+    }
+}
+
+/// Registration of a runtime, it stops receiving reports when it is dropped
+pub struct Listener {
+    output: Output,
+    handler: Option<Arc<DeadlockHandler>>,
+    /// the running jobs of the executor, if awaits should fail
+    running: Option<Arc<Mutex<Running>>>,
+}
+
+impl std::fmt::Debug for Listener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Listener")
+    }
+}
+
+impl Listener {
+    fn notify(&self, report: &DeadlockReport) {
+        match &self.handler {
+            Some(handler) => handler(report),
+            None => {
+                let _ = self.output.write_err(&format!("{}\n", report));
+            }
+        }
+        if let Some(running) = &self.running {
+            let mut running = running.lock();
+            for threads in &report.deadlocks {
+                let ids: Vec<usize> = threads.iter().map(|thread| thread.thread_id).collect();
+                running.fail_threads(&ids);
+            }
+        }
+    }
+}
+
+/// Sets how often the process is checked for deadlocks, this applies to all runtimes
+pub fn set_interval(interval: Duration) {
+    *INTERVAL.lock() = interval.max(Duration::from_millis(1));
+    INTERVAL_CHANGED.notify_all();
+}
+
+/// Starts the detector of the process, if it is not running yet, and registers a runtime
+pub fn listen(
+    detection: DeadlockDetection,
+    output: Output,
+    running: Arc<Mutex<Running>>,
+) -> Option<Arc<Listener>> {
+    if !detection.enabled {
+        return None;
+    }
+    let listener = Arc::new(Listener {
+        output,
+        handler: detection.handler,
+        running: if detection.fail_awaits {
+            Some(running)
+        } else {
+            None
+        },
+    });
+    LISTENERS.lock().push(Arc::downgrade(&listener));
+    START.call_once(|| {
+        thread::spawn(detect);
+    });
+    Some(listener)
+}
+
+fn detect() {
+    loop {
+        {
+            let mut interval = INTERVAL.lock();
+            let timeout = *interval;
+            INTERVAL_CHANGED.wait_for(&mut interval, timeout);
+        }
+        let deadlocks = parking_lot::deadlock::check_deadlock();
+        if deadlocks.is_empty() {
+            continue;
+        }
+        let report = DeadlockReport {
+            deadlocks: deadlocks
+                .iter()
+                .map(|threads| {
+                    threads
+                        .iter()
+                        .map(|thread| DeadlockedThread {
+                            thread_id: thread.thread_id(),
+                            backtrace: format!("{:#?}", thread.backtrace()),
+                        })
+                        .collect()
+                })
+                .collect(),
+        };
+        let listeners: Vec<Arc<Listener>> = {
+            let mut lock = LISTENERS.lock();
+            lock.retain(|listener| listener.strong_count() > 0);
+            lock.iter()
+                .filter_map(|listener| listener.upgrade())
+                .collect()
+        };
+        for listener in listeners {
+            listener.notify(&report);
+        }
+    }
+}
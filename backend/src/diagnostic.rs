@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+/// A position inside of a script
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: Arc<str>,
+    /// 1-based
+    pub line: usize,
+    /// 1-based, counted in chars
+    pub column: usize,
+    /// Amount of chars to underline
+    pub length: usize,
+    pub source_line: String,
+}
+
+impl Location {
+    /// Renders the offending line and underlines the location with carets
+    pub fn snippet(&self) -> String {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let mut underline = String::new();
+        for c in self.source_line.chars().take(self.column - 1) {
+            underline.push(if c == '\t' { '\t' } else { ' ' });
+        }
+        let remaining = self.source_line.chars().count() + 1 - self.column;
+        underline.push_str(&"^".repeat(self.length.min(remaining).max(1)));
+        format!(
+            "{} |\n{} | {}\n{} | {}",
+            gutter, number, self.source_line, gutter, underline
+        )
+    }
+}
+
+impl Location {
+    /// The part of the line which is covered by the location
+    pub fn text(&self) -> String {
+        self.source_line
+            .chars()
+            .skip(self.column - 1)
+            .take(self.length)
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Maps byte offsets of a script to its lines
+#[derive(Debug)]
+pub struct SourceMap {
+    file: Arc<str>,
+    text: String,
+    /// byte offset of the start of every line
+    lines: Vec<usize>,
+}
+
+impl SourceMap {
+    /// Splits `text` into lines, locations refer to it as `file`
+    pub fn new(file: &str, text: &str) -> Self {
+        let mut source_map = SourceMap {
+            file: Arc::from(file),
+            text: String::new(),
+            lines: Vec::new(),
+        };
+        for line in text.lines() {
+            source_map.lines.push(source_map.text.len());
+            source_map.text.push_str(line);
+            source_map.text.push('\n');
+        }
+        source_map
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn locate(&self, start: usize, end: usize) -> Location {
+        let index = match self.lines.binary_search(&start) {
+            Ok(index) => index,
+            Err(0) => 0,
+            Err(index) => index - 1,
+        };
+        match self.lines.get(index) {
+            Some(&line_start) => {
+                let line_end = self
+                    .lines
+                    .get(index + 1)
+                    .copied()
+                    .unwrap_or(self.text.len())
+                    - 1;
+                let start = start.min(line_end);
+                let line_text = &self.text[line_start..line_end];
+                let before = &self.text[line_start..start];
+                let length = self.text[start..end.max(start).min(line_end)]
+                    .chars()
+                    .count();
+                Location {
+                    file: self.file.clone(),
+                    line: index + 1,
+                    column: before.chars().count() + 1,
+                    length,
+                    source_line: line_text.to_owned(),
+                }
+            }
+            None => Location {
+                file: self.file.clone(),
+                line: 1,
+                column: 1,
+                length: 0,
+                source_line: String::new(),
+            },
+        }
+    }
+}
+
+/// A single problem found while compiling a script
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub location: Option<Location>,
+    /// Names of the tokens the parser would have accepted
+    pub expected: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: String) -> Self {
+        Diagnostic {
+            message,
+            location: None,
+            expected: Vec::new(),
+        }
+    }
+
+    pub fn at(message: String, location: Location) -> Self {
+        Diagnostic {
+            message,
+            location: Some(location),
+            expected: Vec::new(),
+        }
+    }
+
+    pub fn from_pest<R: std::fmt::Debug>(
+        error: pest::error::Error<R>,
+        source_map: &SourceMap,
+    ) -> Self {
+        let (start, end) = match error.location {
+            pest::error::InputLocation::Pos(pos) => {
+                // the offending character may be longer than one byte
+                let text = source_map.text();
+                let length = text[pos..].chars().next().map_or(0, char::len_utf8);
+                (pos, (pos + length).min(text.len()))
+            }
+            pest::error::InputLocation::Span(span) => span,
+        };
+        let location = source_map.locate(start, end);
+        match error.variant {
+            pest::error::ErrorVariant::ParsingError { positives, .. } => {
+                let expected: Vec<String> =
+                    positives.iter().map(|rule| format!("{:?}", rule)).collect();
+                let message = if expected.is_empty() {
+                    "unexpected input".to_owned()
+                } else {
+                    format!("expected {}", expected.join(", "))
+                };
+                Diagnostic {
+                    message,
+                    location: Some(location),
+                    expected,
+                }
+            }
+            pest::error::ErrorVariant::CustomError { message } => Diagnostic::at(message, location),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error: {}", self.message)?;
+        if let Some(location) = &self.location {
+            write!(f, "\n --> {}\n{}", location, location.snippet())?;
+        }
+        Ok(())
+    }
+}
+
+/// All problems found while compiling a script
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Diagnostics {
+            diagnostics: vec![diagnostic],
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
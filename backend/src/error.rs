@@ -1,3 +1,6 @@
+use crate::ast::Span;
+use crate::diagnostic::Location;
+use crate::values::ErrorValue;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -24,6 +27,44 @@ pub enum CashError {
     InvalidArguments(String, String),
     #[error("parse error: '{0}'")]
     ParseError(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("can not start command '{0}': {1}")]
+    CannotSpawn(String, String),
+    #[error("step limit of {0} evaluated nodes exceeded")]
+    StepLimitExceeded(u64),
+    #[error("recursion limit of {0} nested calls exceeded")]
+    RecursionLimitExceeded(usize),
+    #[error("timeout of {0}ms exceeded")]
+    Timeout(u64),
+    #[error("interrupted")]
+    Interrupted,
+    #[error("module '{0}' not found")]
+    ModuleNotFound(String),
+    #[error("import cycle: {0}")]
+    ImportCycle(String),
+    #[error("future was already awaited")]
+    AlreadyAwaited,
+    #[error("deadlock detected while awaiting a future")]
+    Deadlock,
+    #[error("no pattern matches {0}")]
+    NoMatch(String),
+    #[error("can not destructure {0}")]
+    CannotDestructure(String),
+    #[error("unknown argument {0}")]
+    UnknownArgument(String),
+    #[error("argument {0} was passed more than once")]
+    DuplicateArgument(String),
+    #[error("can not assign to constant '{0}'")]
+    ConstantAssignment(String),
+    #[error("variable '{0}' is assigned before it is declared")]
+    UndeclaredVariable(String),
+    #[error("invalid format '{0}'")]
+    InvalidFormat(String),
+    #[error("generator was closed")]
+    GeneratorClosed,
+    #[error("{0}")]
+    Thrown(Box<ErrorValue>),
     #[error("{0}")]
     Bug(String),
 }
@@ -32,4 +73,158 @@ impl CashError {
     pub fn boxed<T>(self) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
         Err(Box::new(self))
     }
+
+    /// The name of the variant, which scripts see as the kind of a caught error
+    pub fn kind(&self) -> &str {
+        match self {
+            CashError::InvalidOperation(..) => "invalid_operation",
+            CashError::InvalidLength(..) => "invalid_length",
+            CashError::InvalidType(..) => "invalid_type",
+            CashError::IndexOutOfBounds(..) => "index_out_of_bounds",
+            CashError::KeyNotFound(..) => "key_not_found",
+            CashError::VariableNotFound(..) => "variable_not_found",
+            CashError::InvalidInput(..) => "invalid_input",
+            CashError::InvalidValue(..) => "invalid_value",
+            CashError::InvalidParameterCount(..) => "invalid_parameter_count",
+            CashError::InvalidArguments(..) => "invalid_arguments",
+            CashError::ParseError(..) => "parse_error",
+            CashError::PermissionDenied(..) => "permission_denied",
+            CashError::CannotSpawn(..) => "cannot_spawn",
+            CashError::StepLimitExceeded(..) => "step_limit_exceeded",
+            CashError::RecursionLimitExceeded(..) => "recursion_limit_exceeded",
+            CashError::Timeout(..) => "timeout",
+            CashError::Interrupted => "interrupted",
+            CashError::ModuleNotFound(..) => "module_not_found",
+            CashError::ImportCycle(..) => "import_cycle",
+            CashError::AlreadyAwaited => "already_awaited",
+            CashError::Deadlock => "deadlock",
+            CashError::NoMatch(..) => "no_match",
+            CashError::CannotDestructure(..) => "cannot_destructure",
+            CashError::UnknownArgument(..) => "unknown_argument",
+            CashError::DuplicateArgument(..) => "duplicate_argument",
+            CashError::ConstantAssignment(..) => "constant_assignment",
+            CashError::UndeclaredVariable(..) => "undeclared_variable",
+            CashError::InvalidFormat(..) => "invalid_format",
+            CashError::GeneratorClosed => "generator_closed",
+            CashError::Thrown(value) => &value.kind,
+            CashError::Bug(..) => "bug",
+        }
+    }
+
+    /// Limits of the runtime, interrupts and closed generators can not be caught by scripts
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            CashError::StepLimitExceeded(..)
+                | CashError::RecursionLimitExceeded(..)
+                | CashError::Timeout(..)
+                | CashError::Interrupted
+                | CashError::GeneratorClosed
+        )
+    }
+
+    /// Finds the `CashError` of an error returned by the runtime, looking through `TracedError`
+    pub fn find<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a CashError> {
+        if let Some(traced) = err.downcast_ref::<TracedError>() {
+            return Self::find(traced.inner());
+        }
+        err.downcast_ref::<CashError>()
+    }
+}
+
+/// A frame of the cash-level call stack
+#[derive(Debug, Clone)]
+pub struct StackFrame {
+    /// where the body of the called function starts
+    pub function: Option<Location>,
+    pub call_site: Option<Location>,
+}
+
+/// An error raised while evaluating, together with where it happened
+/// `stack` contains the function calls which led there, innermost first
+#[derive(Debug)]
+pub struct TracedError {
+    pub error: Box<dyn std::error::Error + Send + Sync>,
+    pub location: Option<Location>,
+    pub stack: Vec<StackFrame>,
+}
+
+impl TracedError {
+    /// Attaches the location of a node to an error passing through it
+    /// The innermost node sets the location, the first node outside of a function call sets its call site
+    pub fn trace(
+        err: Box<dyn std::error::Error + Send + Sync>,
+        span: &Span,
+    ) -> Box<dyn std::error::Error + Send + Sync> {
+        match err.downcast::<TracedError>() {
+            Ok(mut traced) => {
+                if traced.location.is_none() {
+                    traced.location = Some(span.location());
+                } else if let Some(frame) = traced.stack.last_mut() {
+                    if frame.call_site.is_none() {
+                        frame.call_site = Some(span.location());
+                    }
+                }
+                traced
+            }
+            Err(err) => Box::new(TracedError {
+                error: err,
+                location: Some(span.location()),
+                stack: Vec::new(),
+            }),
+        }
+    }
+
+    /// Records that an error left the body of a function
+    pub fn push_frame(
+        err: Box<dyn std::error::Error + Send + Sync>,
+        function: Option<&Span>,
+    ) -> Box<dyn std::error::Error + Send + Sync> {
+        let mut traced = match err.downcast::<TracedError>() {
+            Ok(traced) => traced,
+            Err(err) => Box::new(TracedError {
+                error: err,
+                location: None,
+                stack: Vec::new(),
+            }),
+        };
+        traced.stack.push(StackFrame {
+            function: function.map(Span::location),
+            call_site: None,
+        });
+        traced
+    }
+
+    /// The error without any location information
+    pub fn inner(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        &*self.error
+    }
+}
+
+impl std::fmt::Display for TracedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(location) = &self.location {
+            write!(f, "\n --> {}\n{}", location, location.snippet())?;
+        }
+        if !self.stack.is_empty() {
+            write!(f, "\nstack backtrace:")?;
+        }
+        for (i, frame) in self.stack.iter().enumerate() {
+            write!(f, "\n  {}: function", i)?;
+            if let Some(function) = &frame.function {
+                write!(f, " at {}", function)?;
+            }
+            if let Some(call_site) = &frame.call_site {
+                write!(f, ", called at {}: {}", call_site, call_site.text().trim())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TracedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.error)
+    }
 }
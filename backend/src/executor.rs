@@ -1,28 +1,61 @@
 use crate::ast::Node;
+use crate::budget::{Budget, STACK_SIZE};
 use crate::context::LockableContext;
+use crate::error::CashError;
 use crate::value::ValueResult;
 use parking_lot::{const_mutex, const_rwlock, Mutex, RwLock};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::thread;
 use threadpool::ThreadPool;
 
+type Results = Arc<RwLock<HashMap<usize, ValueResult>>>;
+
 #[derive(Debug)]
 pub struct Executor {
     threadpool: Mutex<ThreadPool>,
-    results: Arc<RwLock<HashMap<usize, ValueResult>>>,
+    results: Results,
     counter: usize,
+    /// jobs which were not awaited yet
+    pending: HashSet<usize>,
+    /// jobs which are evaluated right now, shared with the deadlock detector
+    pub running: Arc<Mutex<Running>>,
+}
+
+/// Threads of the running jobs, so awaits only fail when their own job is deadlocked
+#[derive(Debug, Default)]
+pub struct Running {
+    /// job id -> id of the thread evaluating it, as reported by the deadlock detector
+    threads: HashMap<usize, usize>,
+    deadlocked: HashSet<usize>,
+}
+
+impl Running {
+    /// Marks the jobs evaluated by one of `threads` as deadlocked
+    pub fn fail_threads(&mut self, threads: &[usize]) {
+        for (job, thread) in &self.threads {
+            if threads.contains(thread) {
+                self.deadlocked.insert(*job);
+            }
+        }
+    }
 }
 
 impl Default for Executor {
     fn default() -> Executor {
-        let threadpool = const_mutex(ThreadPool::new(num_cpus::get()));
+        let threadpool = threadpool::Builder::new()
+            .num_threads(num_cpus::get())
+            .thread_stack_size(STACK_SIZE)
+            .build();
+        let threadpool = const_mutex(threadpool);
         let results = Arc::new(const_rwlock(HashMap::new()));
         let counter = 0;
         Executor {
             threadpool,
             results,
             counter,
+            pending: HashSet::new(),
+            running: Arc::new(const_mutex(Running::default())),
         }
     }
 }
@@ -31,26 +64,64 @@ impl Executor {
     pub fn register_job(&mut self, node: Arc<dyn Node>, ctx: LockableContext) -> usize {
         let id = self.counter;
         let results = self.results.clone();
+        let running = self.running.clone();
         self.threadpool.lock().execute(move || {
+            #[cfg(feature = "deadlock_detection")]
+            running.lock().threads.insert(id, thread_id::get());
             let result = node.eval(ctx);
+            {
+                let mut running = running.lock();
+                running.threads.remove(&id);
+                running.deadlocked.remove(&id);
+            }
             results.write().insert(id, result);
         });
         self.counter = self.counter.wrapping_add(1);
+        self.pending.insert(id);
         id
     }
 
-    pub fn get_result(&self, id: usize) -> ValueResult {
+    /// Every job may only be awaited once
+    /// The executor does not have to be locked while waiting for the result
+    pub fn waiter(
+        &mut self,
+        id: usize,
+    ) -> Result<Waiter, Box<dyn std::error::Error + Send + Sync>> {
+        if !self.pending.remove(&id) {
+            return CashError::AlreadyAwaited.boxed();
+        }
+        Ok(Waiter {
+            id,
+            results: self.results.clone(),
+            running: self.running.clone(),
+        })
+    }
+}
+
+pub struct Waiter {
+    id: usize,
+    results: Results,
+    running: Arc<Mutex<Running>>,
+}
+
+impl Waiter {
+    /// Gives up once `budget` runs out, the job itself is stopped by the same budget
+    pub fn wait(self, budget: &Budget) -> ValueResult {
         loop {
             {
-                if self.results.read().contains_key(&id) {
+                if self.results.read().contains_key(&self.id) {
                     break;
                 }
             }
+            budget.check_waiting()?;
+            if self.running.lock().deadlocked.remove(&self.id) {
+                return CashError::Deadlock.boxed();
+            }
             thread::sleep(std::time::Duration::from_millis(1));
         }
         self.results
             .write()
-            .remove(&id)
+            .remove(&self.id)
             .expect("Can not happen, checked before")
     }
 }
@@ -1,29 +1,47 @@
 //By default, eval async and wait for completion
 //#BeDefault
 use parking_lot::const_rwlock;
-use std::collections::HashMap;
 use std::sync::Arc;
 
-use glob::glob;
-
 mod ast;
+pub mod budget;
 mod cashstd;
 mod context;
+mod convert;
+#[cfg(feature = "deadlock_detection")]
+pub mod deadlock;
+mod diagnostic;
 mod error;
 mod executor;
+mod modules;
 mod nodes;
+mod output;
+mod permissions;
+mod program;
 mod rules;
 mod value;
-mod values;
+pub mod values;
 
-use crate::context::LockableContext;
+use budget::{Budget, Limits};
 use context::Context;
-use error::CashError;
-use pest::Parser;
-use value::ValueResult;
+use modules::Modules;
+use output::Output;
+use permissions::Permissions;
+
+pub use crate::context::LockableContext;
+pub use budget::InterruptHandle;
+pub use convert::{typed_function, FromValue, IntoValue, IntoValueResult, TypedFunction};
+#[cfg(feature = "deadlock_detection")]
+pub use deadlock::DeadlockReport;
+pub use diagnostic::{Diagnostic, Diagnostics, Location};
+pub use error::{CashError, StackFrame, TracedError};
+pub use program::Program;
+pub use value::{NamedArguments, Value, ValueResult};
 
 pub struct Runtime {
     ctx: LockableContext,
+    #[cfg(feature = "deadlock_detection")]
+    _deadlock_listener: Option<Arc<deadlock::Listener>>,
 }
 
 impl Default for Runtime {
@@ -32,152 +50,351 @@ impl Default for Runtime {
     }
 }
 
-impl Runtime {
-    pub fn new() -> Self {
-        let runtime = Runtime {
-            ctx: Arc::new(const_rwlock(Context::root())),
-        };
-        {
-            let mut ctx = runtime.ctx.write();
-            ctx.set(
-                "PI",
-                Box::new(values::FloatValue {
-                    value: std::f64::consts::PI,
-                }),
-            );
-            ctx.set(
-                "E",
-                Box::new(values::FloatValue {
-                    value: std::f64::consts::E,
-                }),
-            );
+/// Stops the generators and tasks, which are still running
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        self.ctx.read().budget.interrupt();
+    }
+}
+
+/// Configures the capabilities of a `Runtime`
+/// Everything is allowed unless denied explicitly
+#[derive(Debug, Clone)]
+pub struct RuntimeBuilder {
+    permissions: Permissions,
+    limits: Limits,
+    search_path: Vec<std::path::PathBuf>,
+    output: Output,
+    strict: bool,
+    #[cfg(feature = "deadlock_detection")]
+    deadlock: deadlock::DeadlockDetection,
+}
+
+impl Default for RuntimeBuilder {
+    fn default() -> Self {
+        RuntimeBuilder {
+            permissions: Permissions::default(),
+            limits: Limits::default(),
+            search_path: Modules::search_path_from_env(),
+            output: Output::default(),
+            strict: false,
+            #[cfg(feature = "deadlock_detection")]
+            deadlock: Default::default(),
         }
+    }
+}
 
-        #[cfg(feature = "deadlock_detection")]
-        {
-            // only for #[cfg]
-            use parking_lot::deadlock;
-            use std::thread;
-            use std::time::Duration;
-
-            // Create a background thread which checks for deadlocks every 10s
-            thread::spawn(move || loop {
-                thread::sleep(Duration::from_secs(10));
-                let deadlocks = deadlock::check_deadlock();
-                if deadlocks.is_empty() {
-                    continue;
-                }
-
-                println!("{} deadlocks detected", deadlocks.len());
-                for (i, threads) in deadlocks.iter().enumerate() {
-                    println!("Deadlock #{}", i);
-                    for t in threads {
-                        println!("Thread Id {:#?}", t.thread_id());
-                        println!("{:#?}", t.backtrace());
-                    }
-                }
-            });
+impl RuntimeBuilder {
+    /// Starts from a runtime without any capabilities, `CASH_PATH` is ignored
+    pub fn sandboxed() -> Self {
+        RuntimeBuilder {
+            permissions: Permissions::none(),
+            limits: Limits::default(),
+            search_path: Vec::new(),
+            output: Output::default(),
+            strict: false,
+            #[cfg(feature = "deadlock_detection")]
+            deadlock: Default::default(),
         }
+    }
 
-        runtime
+    pub fn allow_processes(mut self, allow: bool) -> Self {
+        self.permissions.processes = allow;
+        self
     }
 
-    fn preprocess(mut text: String) -> String {
-        let mut replacements = HashMap::new();
-        let cwd = std::env::current_dir().expect("Cannot find current working directory");
-        for line in text.lines() {
-            if line.starts_with("include! ") {
-                let (_, path) = line.split_at(9);
-                let mut all_contents = String::new();
-                if replacements.contains_key(path) {
-                    continue;
-                }
-                for entry in glob(path).expect("Failed to read glob pattern") {
-                    match entry {
-                        Ok(path) => {
-                            let path =
-                                std::fs::canonicalize(path).expect("Cannot canonicalize path");
-                            let contents = std::fs::read_to_string(&path)
-                                .expect("Errored while reading file!");
-                            assert!(std::env::set_current_dir(
-                                &path.parent().expect("Cannot find parent of file")
-                            )
-                            .is_ok());
-                            let contents = Self::preprocess(contents);
-                            assert!(std::env::set_current_dir(&cwd).is_ok());
-
-                            all_contents.push_str("\n");
-                            all_contents.push_str(&contents);
-                        }
-                        Err(e) => println!("{:?}", e),
-                    }
-                }
-                replacements.insert(path.to_owned(), all_contents);
-            }
-        }
+    pub fn allow_env_read(mut self, allow: bool) -> Self {
+        self.permissions.env_read = allow;
+        self
+    }
 
-        for (key, value) in replacements {
-            text = text.replace(&format!("include! {}", key), &value);
-        }
-        text
+    pub fn allow_env_write(mut self, allow: bool) -> Self {
+        self.permissions.env_write = allow;
+        self
     }
 
-    pub fn interpret(&mut self, text: String) -> ValueResult {
-        //A is used for debugging purposes
+    pub fn allow_filesystem(mut self, allow: bool) -> Self {
+        self.permissions.filesystem = allow;
+        self
+    }
 
-        let text = text.trim().to_owned();
+    /// Limits filesystem access to the given directories, may be called multiple times
+    /// Also allows filesystem access in general
+    pub fn allow_directory<P: AsRef<std::path::Path>>(mut self, directory: P) -> Self {
+        let directory = std::fs::canonicalize(directory.as_ref())
+            .unwrap_or_else(|_| directory.as_ref().to_path_buf());
+        self.permissions.filesystem = true;
+        self.permissions
+            .allowed_directories
+            .get_or_insert_with(Vec::new)
+            .push(directory);
+        self
+    }
 
-        let text = Self::preprocess(text);
+    pub fn allow_imports(mut self, allow: bool) -> Self {
+        self.permissions.imports = allow;
+        self
+    }
 
-        let parse_result = rules::Language::parse(rules::Rule::Main, &text);
-        if parse_result.is_err() {
-            return CashError::ParseError(format!(
-                "Error occured while parsing input: {:?}",
-                parse_result
-            ))
-            .boxed();
-        }
-        let parse_tree = parse_result.expect("Cannot happen: tested by if");
-        let mut root_nodes = parse_tree.collect::<Vec<_>>();
-        if root_nodes.len() != 1 {
-            return CashError::ParseError(
-                "Error occured while parsing input: Not exactly one root node".to_owned(),
-            )
-            .boxed();
+    /// Adds a directory, which is searched for modules, e.g. `import "ui"`
+    /// Defaults to the directories in `CASH_PATH`
+    pub fn module_path<P: AsRef<std::path::Path>>(mut self, directory: P) -> Self {
+        self.search_path.push(directory.as_ref().to_path_buf());
+        self
+    }
+
+    /// Limits the amount of nodes evaluated by a single execution
+    pub fn max_steps(mut self, steps: Option<u64>) -> Self {
+        self.limits.max_steps = steps;
+        self
+    }
+
+    /// Limits the amount of nested function calls, defaults to `budget::DEFAULT_MAX_DEPTH`
+    /// Without a limit, deep recursion overflows the stack and aborts the process
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.limits.max_depth = depth;
+        self
+    }
+
+    /// Limits the wall-clock time of a single execution
+    pub fn timeout(mut self, timeout: Option<std::time::Duration>) -> Self {
+        self.limits.timeout = timeout;
+        self
+    }
+
+    /// Receives the output of `print` and non-captured pipes instead of the stdout of the process
+    pub fn stdout(mut self, writer: Box<dyn std::io::Write + Send>) -> Self {
+        self.output.set_stdout(writer);
+        self
+    }
+
+    /// Receives the output of `eprint`, the stderr of pipes and reported errors
+    pub fn stderr(mut self, writer: Box<dyn std::io::Write + Send>) -> Self {
+        self.output.set_stderr(writer);
+        self
+    }
+
+    /// Variables have to be declared with `let` or `const` before they are assigned
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Reports deadlocks to the error output, enabled by default
+    /// The interval of the checks is set for the whole process via `deadlock::set_interval`
+    #[cfg(feature = "deadlock_detection")]
+    pub fn detect_deadlocks(mut self, enabled: bool) -> Self {
+        self.deadlock.enabled = enabled;
+        self
+    }
+
+    /// Receives deadlock reports instead of the error output
+    #[cfg(feature = "deadlock_detection")]
+    pub fn deadlock_handler<F>(mut self, handler: F) -> Self
+    where
+        F: 'static + Fn(&DeadlockReport) + Send + Sync,
+    {
+        self.deadlock.enabled = true;
+        self.deadlock.handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// `await`s of jobs, whose thread is part of a detected deadlock, fail with `CashError::Deadlock`
+    #[cfg(feature = "deadlock_detection")]
+    pub fn fail_awaits_on_deadlock(mut self, fail: bool) -> Self {
+        self.deadlock.enabled = true;
+        self.deadlock.fail_awaits = fail;
+        self
+    }
+
+    pub fn build(self) -> Runtime {
+        let mut ctx = Context::root();
+        ctx.permissions = Arc::new(self.permissions);
+        ctx.budget = Arc::new(Budget::new(self.limits));
+        ctx.modules = Arc::new(const_rwlock(Modules::new(self.search_path)));
+        ctx.output = self.output;
+        ctx.strict = self.strict;
+        ctx.set_constants();
+        #[cfg(feature = "deadlock_detection")]
+        let deadlock_listener = deadlock::listen(
+            self.deadlock,
+            ctx.output.clone(),
+            ctx.get_executor().lock().running.clone(),
+        );
+        Runtime {
+            ctx: Arc::new(const_rwlock(ctx)),
+            #[cfg(feature = "deadlock_detection")]
+            _deadlock_listener: deadlock_listener,
         }
-        let root_node = root_nodes.remove(0);
-        let root_span = root_node.as_span();
-        if root_span.start() != 0 || root_span.end() != text.len() {
-            return CashError::ParseError(
-                format!("Error occured while parsing input: Not all of the input was consumed\nConsumed from {} to {}: {}",
-                    root_span.start(),
-                    root_span.end(),
-                    root_span.as_str()
-                )
-            ).boxed();
+    }
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        RuntimeBuilder::default().build()
+    }
+
+    pub fn builder() -> RuntimeBuilder {
+        RuntimeBuilder::default()
+    }
+
+    /// Exposes a host function to scripts under the given name
+    /// The closure may capture state, e.g. an `Arc<Mutex<_>>` shared with the host
+    pub fn register_function<F>(&mut self, name: &str, closure: F)
+    where
+        F: 'static + Fn(Vec<Box<dyn Value>>, LockableContext) -> ValueResult + Send + Sync,
+    {
+        let functions = self.ctx.read().functions.clone();
+        functions.write().insert(
+            name.to_owned(),
+            values::BuiltInFunction::from_closure(closure),
+        );
+    }
+
+    /// Like `register_function`, but the closure also receives the named arguments of a call
+    pub fn register_named_function<F>(&mut self, name: &str, closure: F)
+    where
+        F: 'static
+            + Fn(Vec<Box<dyn Value>>, NamedArguments, LockableContext) -> ValueResult
+            + Send
+            + Sync,
+    {
+        let functions = self.ctx.read().functions.clone();
+        functions.write().insert(
+            name.to_owned(),
+            values::BuiltInFunction::with_named(closure),
+        );
+    }
+
+    /// Exposes a Rust function with typed parameters to scripts
+    /// Arguments and the return value are converted via `FromValue` and `IntoValue`
+    pub fn register_typed_function<Args, F: TypedFunction<Args>>(
+        &mut self,
+        name: &str,
+        function: F,
+    ) {
+        let functions = self.ctx.read().functions.clone();
+        functions
+            .write()
+            .insert(name.to_owned(), typed_function(function));
+    }
+
+    /// Reads a global variable of the scripts run by this runtime
+    pub fn get_global(&self, name: &str) -> Option<Box<dyn Value>> {
+        self.ctx.read().get_self(name)
+    }
+
+    /// Sets a global variable, which scripts can read afterwards
+    /// Fails for environment variables if the runtime may not write them
+    pub fn set_global<T: IntoValue>(
+        &mut self,
+        name: &str,
+        value: T,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.ctx.write().set(name, value.into_value())
+    }
+
+    /// All global variables, ordered by name
+    pub fn globals(&self) -> impl Iterator<Item = (String, Box<dyn Value>)> {
+        let ctx = self.ctx.read();
+        let mut globals: Vec<_> = ctx
+            .vars()
+            .map(|(name, value)| (name.clone(), (**value).clone()))
+            .collect();
+        globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        globals.into_iter()
+    }
+
+    /// A handle which can cancel the current execution from another thread, e.g. a signal handler
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle::new(self.ctx.read().budget.clone())
+    }
+
+    /// The working directory of scripts, independent of the process cwd
+    pub fn cwd(&self) -> std::path::PathBuf {
+        self.ctx.read().get_cwd()
+    }
+
+    /// Relative paths are resolved against the current working directory of the runtime
+    pub fn set_cwd<P: AsRef<std::path::Path>>(&mut self, path: P) -> std::io::Result<()> {
+        let path = std::fs::canonicalize(self.ctx.read().resolve_path(path))?;
+        *self.ctx.read().cwd.write() = path;
+        Ok(())
+    }
+
+    /// Writes an error or diagnostics to the error output of this runtime
+    pub fn report_error<E: std::fmt::Display + ?Sized>(&self, error: &E) {
+        let _ = self.ctx.read().output.write_err(&format!("{}\n", error));
+    }
+
+    /// Calls a function visible from the global scope, e.g. a closure defined by a script
+    pub fn call_function(&mut self, name: &str, args: Vec<Box<dyn Value>>) -> ValueResult {
+        self.ctx.read().budget.start();
+        let function = self.ctx.read().get(name);
+        match function {
+            Some(function) => on_interpreter_stack(|| function.call(args, self.ctx.clone())),
+            None => CashError::VariableNotFound(name.to_owned()).boxed(),
         }
-        let tree_result = ast::make_ast(
-            root_node
-                .into_inner()
-                .next()
-                .expect("Main should have a Block"),
-        );
-        // println!("{:?}",tree_result);
-        if tree_result.is_err() {
-            return CashError::ParseError(format!(
-                "Error occured while parsing input: {:?}",
-                tree_result
-            ))
-            .boxed();
+    }
+
+    /// Parses a script without running it
+    /// Programs do not depend on a runtime, they can be executed by any of them
+    pub fn compile(text: &str) -> Result<Program, Diagnostics> {
+        Program::compile(text, "<input>")
+    }
+
+    /// Like `compile`, but reports diagnostics for the given file name
+    /// Imports are resolved relative to the file, if it exists
+    pub fn compile_source(text: &str, file: &str) -> Result<Program, Diagnostics> {
+        Program::compile(text, file)
+    }
+
+    /// Runs a compiled program in the global context of this runtime
+    pub fn execute(&mut self, program: &Program) -> ValueResult {
+        self.ctx.read().budget.start();
+        on_interpreter_stack(|| program.eval(self.ctx.clone()))
+    }
+
+    /// Trailing whitespace is trimmed before compiling
+    /// Leading whitespace is kept, so diagnostics refer to the lines of `text`
+    pub fn interpret(&mut self, text: String) -> ValueResult {
+        match Self::compile(text.trim_end()) {
+            Ok(program) => self.execute(&program),
+            Err(diagnostics) => CashError::ParseError(diagnostics.to_string()).boxed(),
         }
-        let tree = tree_result.expect("Cannot happen: tested by if");
-        tree.eval(self.ctx.clone())
     }
 }
 
+/// Evaluates on a thread with a stack of `budget::STACK_SIZE`, which the default depth limit fits into
+fn on_interpreter_stack<F>(eval: F) -> ValueResult
+where
+    F: FnOnce() -> ValueResult + Send,
+{
+    std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new()
+            .stack_size(budget::STACK_SIZE)
+            .spawn_scoped(scope, eval)?;
+        thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    /// Interprets scripts in `runtime` and renders their results
+    fn runner(
+        runtime: &mut Runtime,
+    ) -> impl FnMut(&str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> + '_ {
+        move |text| {
+            runtime
+                .interpret(text.to_owned())
+                .map(|value| value.to_string())
+        }
+    }
+
     #[test]
     fn simple() {
         let mut runtime = Runtime::new();
@@ -206,4 +423,870 @@ mod tests {
             .to_owned(),
         );
     }
+
+    #[test]
+    fn register_function() {
+        use parking_lot::Mutex;
+        let mut runtime = Runtime::new();
+        let counter = Arc::new(Mutex::new(0));
+        let captured = counter.clone();
+        runtime.register_function("count", move |params, _ctx| {
+            *captured.lock() += params.len() as i64;
+            values::IntegerValue::boxed(*captured.lock())
+        });
+        let result = runtime
+            .interpret("count(1, 2)\ncount(3)".to_owned())
+            .unwrap();
+        assert_eq!(result.to_string(), "3");
+        assert_eq!(*counter.lock(), 3);
+    }
+
+    #[test]
+    fn typed_function() {
+        let mut runtime = Runtime::new();
+        runtime.register_typed_function("repeat", |count: i64, text: String| {
+            vec![text; count as usize]
+        });
+        let result = runtime.interpret("repeat(2, 'ab')".to_owned()).unwrap();
+        let result = Vec::<String>::from_value(result).unwrap();
+        assert_eq!(result, vec!["ab".to_owned(), "ab".to_owned()]);
+
+        let err = runtime.interpret("repeat('ab', 2)".to_owned()).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("invalid arguments: found 'string', but needs 'integer'"));
+        assert!(runtime.interpret("repeat(2)".to_owned()).is_err());
+
+        let value = (1i64, Some(2.5f64), true).into_value();
+        assert_eq!(value.to_string(), "[1, 2.5, true]");
+        let (a, b, c) = <(i64, Option<f64>, bool)>::from_value(value).unwrap();
+        assert_eq!((a, b, c), (1, Some(2.5), true));
+    }
+
+    #[test]
+    fn globals() {
+        let mut runtime = Runtime::new();
+        runtime.set_global("offset", 10i64).unwrap();
+        runtime
+            .interpret(
+                r#"
+        fibonacci = (n) -> {
+            if n <= 1 {
+                1
+            } else {
+                fibonacci(n-2) + fibonacci(n-1)
+            }
+        }
+        shifted = fibonacci(5) + offset
+        "#
+                .to_owned(),
+            )
+            .unwrap();
+        let shifted = i64::from_value(runtime.get_global("shifted").unwrap()).unwrap();
+        assert_eq!(shifted, 18);
+        let result = runtime
+            .call_function("fibonacci", vec![6i64.into_value()])
+            .unwrap();
+        assert_eq!(i64::from_value(result).unwrap(), 13);
+        let names: Vec<String> = runtime.globals().map(|(name, _)| name).collect();
+        assert!(names.contains(&"fibonacci".to_owned()));
+        assert!(runtime.call_function("missing", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn compile() {
+        assert!(Runtime::compile("a = (").is_err());
+        let program = Runtime::compile("counter = counter + 1").unwrap();
+        let shared = program.clone();
+        let handle = std::thread::spawn(move || {
+            let mut other = Runtime::new();
+            other.set_global("counter", 10i64).unwrap();
+            other.execute(&shared).unwrap().to_string()
+        });
+        let mut runtime = Runtime::new();
+        runtime.set_global("counter", 0i64).unwrap();
+        for _ in 0..3 {
+            runtime.execute(&program).unwrap();
+        }
+        assert_eq!(runtime.get_global("counter").unwrap().to_string(), "3");
+        assert_eq!(handle.join().unwrap(), "11");
+        let result = runtime.interpret("\n  counter + 1 \n\n".to_owned());
+        assert_eq!(result.unwrap().to_string(), "4");
+    }
+
+    #[test]
+    fn diagnostics() {
+        let err = Runtime::compile_source("a = 1\nb = [1, 2]\n\tc = (2 + )\n", "script.cash")
+            .unwrap_err();
+        let diagnostic = &err.diagnostics[0];
+        let location = diagnostic.location.as_ref().unwrap();
+        assert_eq!(&*location.file, "script.cash");
+        assert_eq!((location.line, location.column), (3, 11));
+        assert!(!diagnostic.expected.is_empty());
+        assert!(err.to_string().contains(" --> script.cash:3:11"));
+        assert!(err
+            .to_string()
+            .contains("3 | \tc = (2 + )\n  | \t         ^"));
+        let err = Runtime::compile_source("a = é", "script.cash").unwrap_err();
+        let location = err.diagnostics[0].location.as_ref().unwrap();
+        assert_eq!((location.column, location.length), (5, 1));
+        let err = Runtime::compile_source("", "empty.cash").unwrap_err();
+        let location = err.diagnostics[0].location.as_ref().unwrap();
+        assert_eq!(&*location.file, "empty.cash");
+    }
+
+    #[test]
+    fn traceback() {
+        let mut runtime = Runtime::new();
+        let err = runtime
+            .interpret(
+                r#"
+        inner = (v) -> {
+            v + missing
+        }
+        outer = (list) -> {
+            map(list, (v, i) -> { inner(v) })
+        }
+        outer([1, 2])
+        "#
+                .to_owned(),
+            )
+            .unwrap_err();
+        let traced = err.downcast_ref::<TracedError>().unwrap();
+        let location = traced.location.as_ref().unwrap();
+        assert_eq!((location.line, location.column), (3, 17));
+        assert_eq!(traced.stack.len(), 3);
+        let call_sites: Vec<usize> = traced
+            .stack
+            .iter()
+            .map(|frame| frame.call_site.as_ref().unwrap().line)
+            .collect();
+        assert_eq!(call_sites, vec![6, 6, 8]);
+        assert!(err
+            .to_string()
+            .contains("called at <input>:8:9: outer([1, 2])"));
+    }
+
+    #[test]
+    fn sandbox() {
+        let mut runtime = RuntimeBuilder::sandboxed()
+            .allow_directory(std::env::temp_dir())
+            .build();
+        let denied = |runtime: &mut Runtime, code: &str| match runtime.interpret(code.to_owned()) {
+            Err(err) => err.to_string().contains("permission denied"),
+            Ok(_) => false,
+        };
+        assert!(denied(&mut runtime, "$ echo('hi')"));
+        assert!(denied(&mut runtime, "$ echo hi | cat()"));
+        assert!(denied(&mut runtime, "$HOME"));
+        assert!(denied(&mut runtime, "$CASH_SANDBOX = 1"));
+        assert!(denied(&mut runtime, "[1 for $CASH_SANDBOX in [1]]"));
+        assert!(denied(
+            &mut runtime,
+            "f = (...$CASH_SANDBOX) -> { 1 }\nf(1)"
+        ));
+        assert!(denied(&mut runtime, "match 1 {\n$CASH_SANDBOX => 1\n}"));
+        assert!(denied(
+            &mut runtime,
+            "try {\nthrow 1\n} catch $CASH_SANDBOX {\n1\n}"
+        ));
+        assert!(runtime.set_global("$CASH_SANDBOX", 1i64).is_err());
+        assert!(std::env::var("CASH_SANDBOX").is_err());
+        assert!(denied(&mut runtime, "cd('/')"));
+        assert!(denied(
+            &mut runtime,
+            "import \"./examples/helloworld.cash\""
+        ));
+        assert_eq!(
+            runtime
+                .interpret("exists('$HOME')".to_owned())
+                .unwrap()
+                .to_string(),
+            "false"
+        );
+        assert_eq!(
+            runtime.interpret("1 + 2".to_owned()).unwrap().to_string(),
+            "3"
+        );
+        // bindings of environment variables write the environment
+        let mut runtime = Runtime::new();
+        runtime
+            .interpret("match 1 {\n$CASH_MATCHED => 0\n}".to_owned())
+            .unwrap();
+        let matched = runtime
+            .interpret("value = $CASH_MATCHED\nvalue".to_owned())
+            .unwrap();
+        assert_eq!(matched.to_string(), "1");
+    }
+
+    #[test]
+    fn budgets() {
+        let kind = |err: Box<dyn std::error::Error + Send + Sync>| match CashError::find(&*err) {
+            Some(CashError::StepLimitExceeded(_)) => "steps",
+            Some(CashError::RecursionLimitExceeded(_)) => "depth",
+            Some(CashError::Timeout(_)) => "timeout",
+            _ => "other",
+        };
+        let mut runtime = Runtime::builder().max_steps(Some(1000)).build();
+        let err = runtime
+            .interpret("while true { x = 1 }".to_owned())
+            .unwrap_err();
+        assert_eq!(kind(err), "steps");
+        // the budget is reset for every execution
+        assert!(runtime.interpret("1 + 2".to_owned()).is_ok());
+
+        let mut runtime = Runtime::builder().max_depth(Some(64)).build();
+        let err = runtime
+            .interpret("f = (n) -> { f(n + 1) }\nf(0)".to_owned())
+            .unwrap_err();
+        assert_eq!(kind(err), "depth");
+        // the default limit allows deep recursion, but fails before the stack overflows
+        let mut runtime = Runtime::new();
+        runtime
+            .interpret("f = (n) -> { if n == 0 { 0 } else { f(n - 1) + 1 } }".to_owned())
+            .unwrap();
+        let result = runtime.interpret("f(1500)".to_owned());
+        assert_eq!(result.unwrap().to_string(), "1500");
+        let err = runtime.interpret("f(100000)".to_owned()).unwrap_err();
+        assert_eq!(kind(err), "depth");
+        // generators and tasks run on threads of their own
+        let err = runtime
+            .interpret("g = () -> { yield f(100000) }\n[v for v in g()]".to_owned())
+            .unwrap_err();
+        assert_eq!(kind(err), "depth");
+        let err = runtime
+            .interpret("task = async f(100000)\nawait task".to_owned())
+            .unwrap_err();
+        assert_eq!(kind(err), "depth");
+
+        let mut runtime = Runtime::builder()
+            .timeout(Some(std::time::Duration::from_millis(50)))
+            .build();
+        let err = runtime
+            .interpret("while true { x = 1 }".to_owned())
+            .unwrap_err();
+        assert_eq!(kind(err), "timeout");
+        // waiting for processes and tasks counts as well
+        let err = runtime.interpret("$ sleep \"3\"".to_owned()).unwrap_err();
+        assert_eq!(kind(err), "timeout");
+        let err = runtime
+            .interpret("task = async $ sleep \"3\"\nawait task".to_owned())
+            .unwrap_err();
+        assert_eq!(kind(err), "timeout");
+    }
+
+    #[test]
+    fn interrupt() {
+        let mut runtime = Runtime::new();
+        let handle = runtime.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            handle.interrupt();
+        });
+        let err = runtime
+            .interpret("while true { x = 1 }".to_owned())
+            .unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::Interrupted)
+        ));
+        interrupter.join().unwrap();
+
+        let handle = runtime.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            handle.interrupt();
+        });
+        let start = std::time::Instant::now();
+        let err = runtime.interpret("$ sleep \"10\"".to_owned()).unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::Interrupted)
+        ));
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        interrupter.join().unwrap();
+
+        // the next execution is not affected
+        runtime
+            .interpret("output = $ echo \"abc\" | tr \"a\" \"x\"".to_owned())
+            .unwrap();
+        let output = runtime.get_global("output").unwrap();
+        assert_eq!(output.to_string(), "xbc");
+    }
+
+    #[test]
+    fn missing_command() {
+        let mut runtime = Runtime::new();
+        let start = std::time::Instant::now();
+        for code in &[
+            "$ nonexistent_cmd_xyz",
+            "$ sleep \"10\" | nonexistent_cmd_xyz",
+        ] {
+            let err = runtime.interpret(code.to_string()).unwrap_err();
+            assert!(matches!(
+                CashError::find(&*err),
+                Some(CashError::CannotSpawn(..))
+            ));
+        }
+        // the already started sleep is killed
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn modules() {
+        let directory = std::env::temp_dir().join(format!("cash_modules_{}", std::process::id()));
+        let library = directory.join("lib");
+        std::fs::create_dir_all(&library).unwrap();
+        let write = |path: &std::path::Path, text: &str| std::fs::write(path, text).unwrap();
+        write(
+            &directory.join("main.cash"),
+            "m = import \"./ui.cash\"\nm::ui::style(\"text\")",
+        );
+        write(
+            &directory.join("ui.cash"),
+            "counter = import \"counter\"\nprefix = \"> \"\nui = { \"style\": (text) -> { prefix + text } }",
+        );
+        write(&library.join("counter.cash"), "count = 1");
+        write(&directory.join("a.cash"), "b = import \"./b.cash\"");
+        write(&directory.join("b.cash"), "a = import \"./a.cash\"");
+
+        let mut runtime = Runtime::builder().module_path(&library).build();
+        let main = directory.join("main.cash");
+        let program = Runtime::compile_source(
+            &std::fs::read_to_string(&main).unwrap(),
+            &main.display().to_string(),
+        )
+        .unwrap();
+        assert_eq!(runtime.execute(&program).unwrap().to_string(), "> text");
+        // module globals stay inside of the module
+        assert!(runtime.get_global("prefix").is_none());
+
+        // modules are evaluated once
+        write(&library.join("counter.cash"), "count = 2");
+        let count = format!("import \"{}\"", library.join("counter.cash").display());
+        let counter = runtime.interpret(count).unwrap();
+        assert_eq!(counter.to_string(), "{count: 1}");
+
+        let cycle = format!("import \"{}\"", directory.join("a.cash").display());
+        let err = runtime.interpret(cycle).unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::ImportCycle(_))
+        ));
+        // concurrent imports of the same module are not a cycle
+        let slow = directory.join("slow.cash");
+        write(&slow, "$ sleep \"0.2\"\ndone = true");
+        let concurrent = format!(
+            "first = async import \"{0}\"\nsecond = import \"{0}\"\n[await first, second]",
+            slow.display()
+        );
+        let modules = runtime.interpret(concurrent).unwrap();
+        assert_eq!(modules.to_string(), "[{done: true}, {done: true}]");
+        let err = runtime
+            .interpret("import \"./missing\"".to_owned())
+            .unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::ModuleNotFound(_))
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn output() {
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<parking_lot::Mutex<Vec<u8>>>);
+        impl std::io::Write for Buffer {
+            fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().extend_from_slice(bytes);
+                Ok(bytes.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        impl Buffer {
+            fn text(&self) -> String {
+                String::from_utf8_lossy(&self.0.lock()).to_string()
+            }
+        }
+
+        let (stdout, stderr) = (Buffer::default(), Buffer::default());
+        let mut runtime = Runtime::builder()
+            .stdout(Box::new(stdout.clone()))
+            .stderr(Box::new(stderr.clone()))
+            .build();
+        runtime
+            .interpret("print(\"a\", 1)\neprint(\"oops\")\n$ echo \"piped\"".to_owned())
+            .unwrap();
+        runtime
+            .interpret("$ sh \"-c\" \"echo failed >&2\"".to_owned())
+            .unwrap();
+        let err = runtime.interpret("x = y".to_owned()).unwrap_err();
+        runtime.report_error(&err);
+        assert_eq!(stdout.text(), "a1\npiped\n");
+        assert!(stderr.text().starts_with("oops\nfailed\nvariable"));
+    }
+
+    #[test]
+    fn working_directory() {
+        let directory = std::env::temp_dir().join(format!("cash_cwd_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("a")).unwrap();
+        std::fs::create_dir_all(directory.join("b")).unwrap();
+        let directory = std::fs::canonicalize(directory).unwrap();
+        let process_cwd = std::env::current_dir().unwrap();
+
+        let mut first = Runtime::new();
+        let mut second = Runtime::new();
+        first.set_cwd(&directory).unwrap();
+        second.set_cwd(&directory).unwrap();
+        first.interpret("cd(\"a\")".to_owned()).unwrap();
+        second.interpret("cd(\"b\")".to_owned()).unwrap();
+        assert_eq!(first.cwd(), directory.join("a"));
+        assert_eq!(second.cwd(), directory.join("b"));
+        assert_eq!(std::env::current_dir().unwrap(), process_cwd);
+
+        first.interpret("dir = $ pwd".to_owned()).unwrap();
+        let dir = first.get_global("dir").unwrap().to_string();
+        assert_eq!(dir, directory.join("a").display().to_string());
+
+        // async tasks get their own working directory
+        first
+            .interpret("task = async cd(\"..\")\nawait task\ndir = cwd()".to_owned())
+            .unwrap();
+        let dir = first.get_global("dir").unwrap().to_string();
+        assert_eq!(dir, directory.join("a").display().to_string());
+        // also when they call functions defined outside of the task
+        first
+            .interpret(
+                "up = () -> { cd(\"..\") }\ntask = async up()\nawait task\ndir = cwd()".to_owned(),
+            )
+            .unwrap();
+        let dir = first.get_global("dir").unwrap().to_string();
+        assert_eq!(dir, directory.join("a").display().to_string());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn await_once() {
+        let mut runtime = Runtime::new();
+        let err = runtime
+            .interpret("f = async 1 + 2\nawait f\nawait f".to_owned())
+            .unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::AlreadyAwaited)
+        ));
+    }
+
+    #[test]
+    fn short_circuit() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        assert_eq!(run("exists(\"x\") && x > 3").unwrap(), "false");
+        assert_eq!(run("true || x").unwrap(), "true");
+        assert_eq!(run("1 < 2 && 2 < 3 || false").unwrap(), "true");
+        assert_eq!(run("type(none)").unwrap(), "none");
+        assert_eq!(run("none ?? 1 + 2").unwrap(), "3");
+        assert_eq!(run("4 ?? x").unwrap(), "4");
+        run("cfg = {\"db\": {\"port\": 5432}}").unwrap();
+        assert_eq!(
+            run("cfg?::db?::host ?? \"localhost\"").unwrap(),
+            "localhost"
+        );
+        assert_eq!(
+            run("cfg?::cache?::host ?? \"localhost\"").unwrap(),
+            "localhost"
+        );
+        assert_eq!(run("cfg?.[\"db\"]?::port").unwrap(), "5432");
+        assert_eq!(run("f = none\nf?.(1) ?? 0").unwrap(), "0");
+        assert!(run("cfg::cache::host").is_err());
+        // unlike `&` and `|`, the logical operators only take booleans
+        for code in &["1 || 4", "1 && 2", "true && 1", "false || \"a\""] {
+            let err = run(code).unwrap_err();
+            assert!(matches!(
+                CashError::find(&*err),
+                Some(CashError::InvalidType(..))
+            ));
+        }
+        assert_eq!(run("1 | 4").unwrap(), "5");
+    }
+
+    #[test]
+    fn pattern_matching() {
+        let mut runtime = Runtime::new();
+        runtime
+            .interpret(
+                r#"describe = (value) -> {
+    match value {
+        none => "nothing"
+        0 => "zero"
+        -1 => "minus one"
+        1..10 => "small"
+        integer(n) if n < 0 => "negative ${n}"
+        integer(_) => "big"
+        "yes" => "agreed"
+        string(s) => "text ${s}"
+        [] => "empty"
+        [first, ..rest] => "${first} and ${len(rest)} more"
+        {name, age: integer(age)} => "${name} is ${age}"
+        {name} => name
+    }
+}"#
+                .to_owned(),
+            )
+            .unwrap();
+        let mut describe = |arg: &str| {
+            runtime
+                .interpret(format!("describe({})", arg))
+                .map(|value| value.to_string())
+        };
+        assert_eq!(describe("none").unwrap(), "nothing");
+        assert_eq!(describe("0").unwrap(), "zero");
+        assert_eq!(describe("-1").unwrap(), "minus one");
+        assert_eq!(describe("5").unwrap(), "small");
+        assert_eq!(describe("-7").unwrap(), "negative -7");
+        assert_eq!(describe("42").unwrap(), "big");
+        assert_eq!(describe("\"yes\"").unwrap(), "agreed");
+        assert_eq!(describe("\"no\"").unwrap(), "text no");
+        assert_eq!(describe("[]").unwrap(), "empty");
+        assert_eq!(describe("[1, 2, 3]").unwrap(), "1 and 2 more");
+        assert_eq!(
+            describe("{\"name\": \"bob\", \"age\": 3}").unwrap(),
+            "bob is 3"
+        );
+        assert_eq!(describe("{\"name\": \"alice\"}").unwrap(), "alice");
+        let err = describe("true").unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::NoMatch(_))
+        ));
+    }
+
+    #[test]
+    fn destructuring() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        run("[first, ..rest] = [1, 2, 3]").unwrap();
+        assert_eq!(run("\"${first} ${rest}\"").unwrap(), "1 [2, 3]");
+        run("{host, port: p} = {\"host\": \"localhost\", \"port\": 80}").unwrap();
+        assert_eq!(run("\"${host}:${p}\"").unwrap(), "localhost:80");
+        // keys are unescaped like string literals
+        run(r#"{"a\"b": q} = {"a\"b": 1}"#).unwrap();
+        assert_eq!(run("q").unwrap(), "1");
+        run("a = 1\nb = 2\na, b = b, a").unwrap();
+        assert_eq!(run("\"${a} ${b}\"").unwrap(), "2 1");
+        run("sum = 0\nfor [x, y] in [[1, 2], [3, 4]] { sum += x * y }").unwrap();
+        assert_eq!(run("sum").unwrap(), "14");
+        run("add = ([x, y], {z}) -> { x + y + z }").unwrap();
+        assert_eq!(run("add([1, 2], {\"z\": 3})").unwrap(), "6");
+        let err = run("[x, y] = [1]").unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::CannotDestructure(_))
+        ));
+    }
+
+    #[test]
+    fn call_arguments() {
+        let mut runtime = Runtime::new();
+        runtime.register_named_function("connect", |params, named, _ctx| {
+            let port = named
+                .get("port")
+                .map(|port| port.to_string())
+                .unwrap_or_else(|| "80".to_owned());
+            values::StringValue::boxed(format!("{}:{}", params[0], port))
+        });
+        let mut run = runner(&mut runtime);
+        run("log = (level, ...parts) -> { \"${level}: ${parts}\" }").unwrap();
+        assert_eq!(run("log(\"info\", 1, 2)").unwrap(), "info: [1, 2]");
+        assert_eq!(run("log(\"info\")").unwrap(), "info: []");
+        run("sub = (a, b = 1) -> { a - b }").unwrap();
+        assert_eq!(run("sub(b = 10, a = 3)").unwrap(), "-7");
+        assert_eq!(run("sub(...[5, 2])").unwrap(), "3");
+        assert_eq!(run("sub(**{\"a\": 5})").unwrap(), "4");
+        assert_eq!(run("add = (a, b) -> { a + b }\nadd(1)(2)").unwrap(), "3");
+        assert_eq!(run("add(b = 2)(1)").unwrap(), "3");
+        assert_eq!(run("connect(\"host\", port = 22)").unwrap(), "host:22");
+        let err = run("sub(1, c = 2)").unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::UnknownArgument(_))
+        ));
+        let err = run("sub(1, a = 2)").unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::DuplicateArgument(_))
+        ));
+        // commands only take positional arguments
+        assert!(Runtime::compile("print($ echo(a = 1))").is_err());
+    }
+
+    #[test]
+    fn try_catch() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        run("cleaned = false").unwrap();
+        let caught = run(r#"try {
+    {"a": 1}["b"]
+} catch err {
+    "${err::kind} ${type(err::location)}"
+} finally {
+    cleaned = true
+}"#)
+        .unwrap();
+        assert_eq!(caught, "key_not_found string");
+        assert_eq!(run("cleaned").unwrap(), "true");
+        let thrown = run(r#"try {
+    throw error("failed", {"code": 2})
+} catch err {
+    "${err::kind}: ${err} ${err::data::code}"
+}"#)
+        .unwrap();
+        assert_eq!(thrown, "error: failed 2");
+        assert_eq!(run("try { throw 5 } catch e { e::data + 1 }").unwrap(), "6");
+        let err = run("try { throw \"boom\" } finally { cleaned = false }").unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::Thrown(error)) if error.message == "boom"
+        ));
+        assert_eq!(run("cleaned").unwrap(), "false");
+    }
+
+    #[test]
+    fn iterators() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        let first = run(r#"found = none
+for i in 0..1_000_000_000 {
+    if i * i > 50 {
+        found = i
+        break
+    }
+}
+found"#)
+        .unwrap();
+        assert_eq!(first, "8");
+        run(r#"naturals = () -> {
+    n = 0
+    while true {
+        yield n
+        n += 1
+    }
+}"#)
+        .unwrap();
+        let taken = run(r#"taken = []
+for n in filter(naturals(), (n, i) -> { n % 2 == 0 }) {
+    taken = push(taken, n)
+    if len(taken) == 3 { break }
+}
+taken"#)
+        .unwrap();
+        assert_eq!(taken, "[0, 2, 4]");
+        run("squares = (limit) -> { for i in 0..limit { yield i * i } }").unwrap();
+        assert_eq!(run("type(squares(3))").unwrap(), "iterator");
+        assert_eq!(
+            run("reduce(squares(4), (sum, n, i) -> { sum + n }, 0)").unwrap(),
+            "14"
+        );
+        assert_eq!(
+            run("map(0..3, (n, i) -> { n * 10 })").unwrap(),
+            "[0, 10, 20]"
+        );
+        assert_eq!(
+            run("filter(\"a-b\", (c, i) -> { c != \"-\" })").unwrap(),
+            "[a, b]"
+        );
+        assert_eq!(run("type(map(0.., (n, i) -> { n }))").unwrap(), "iterator");
+
+        // waiting for the next item counts towards the timeout
+        let mut runtime = Runtime::builder()
+            .timeout(Some(std::time::Duration::from_millis(50)))
+            .build();
+        runtime.register_function("block", |_, _| {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            values::NoneValue::boxed()
+        });
+        let start = std::time::Instant::now();
+        let err = runtime
+            .interpret("slow = () -> { block()\nyield 1 }\nfor n in slow() { n }".to_owned())
+            .unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::Timeout(_))
+        ));
+        assert!(start.elapsed() < std::time::Duration::from_millis(400));
+
+        // a suspended generator does not outlive an interrupt of its runtime
+        let mut runtime = Runtime::new();
+        let handle = runtime.interrupt_handle();
+        runtime
+            .interpret(
+                "pair = () -> { yield 1\nyield 2 }\ng = pair()\nfor n in g { break }".to_owned(),
+            )
+            .unwrap();
+        handle.interrupt();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let err = runtime.interpret("[n for n in g]".to_owned()).unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::Interrupted)
+        ));
+    }
+
+    #[test]
+    fn comprehensions() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        assert_eq!(
+            run("[x * x for x in 0..6 if x % 2 == 0]").unwrap(),
+            "[0, 4, 16]"
+        );
+        assert_eq!(run("[c + c for c in \"abc\"]").unwrap(), "[aa, bb, cc]");
+        assert_eq!(
+            run("[[x, y] for x in 0..2 for y in 0..3 if y > x]").unwrap(),
+            "[[0, 1], [0, 2], [1, 2]]"
+        );
+        run("x = 10").unwrap();
+        assert_eq!(
+            run("doubled = {k: v * 2 for k, v in {\"a\": 1}}\ndoubled::a").unwrap(),
+            "2"
+        );
+        assert_eq!(run("[x for [x, _] in [[1, 2], [3, 4]]]\nx").unwrap(), "10");
+        // environment variables are written like in an assignment
+        run("[1 for $CASH_COMPREHENSION in [\"a\"]]").unwrap();
+        assert_eq!(std::env::var("CASH_COMPREHENSION").unwrap(), "a");
+    }
+
+    #[test]
+    fn ranges() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        assert_eq!(run("[x for x in 1..=4]").unwrap(), "[1, 2, 3, 4]");
+        assert_eq!(run("[x for x in 0..10 step 4]").unwrap(), "[0, 4, 8]");
+        assert_eq!(run("[x for x in 3..0]").unwrap(), "[3, 2, 1]");
+        assert_eq!(
+            run("n = 2\n[x for x in n - 1..n * 2]").unwrap(),
+            "[1, 2, 3]"
+        );
+        assert_eq!(run("1..=9 step 2").unwrap(), "1..=9 step 2");
+        assert!(run("0..5 step 0").is_err());
+        run("xs = [1, 2, 3, 4, 5]").unwrap();
+        assert_eq!(run("xs[-2..]").unwrap(), "[4, 5]");
+        assert_eq!(run("xs[..2]").unwrap(), "[1, 2]");
+        assert_eq!(run("xs[3..0]").unwrap(), "[4, 3, 2]");
+        assert_eq!(run("xs[..100]").unwrap(), "[1, 2, 3, 4, 5]");
+        assert_eq!(run("\"hello\"[1..-1]").unwrap(), "ell");
+        assert_eq!(run("xs[-2..] = 0\nxs").unwrap(), "[1, 2, 3, 0, 0]");
+        assert_eq!(run("4 in 1..=4").unwrap(), "true");
+        assert_eq!(run("3 in 0..10 step 2").unwrap(), "false");
+        assert_eq!(run("1000 in 5..").unwrap(), "true");
+        assert_eq!(
+            run("match -3 { ..-5 => \"low\", -5..=0 => \"mid\", _ => \"high\" }").unwrap(),
+            "mid"
+        );
+    }
+
+    #[test]
+    fn pipeline() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        run("add = (a, b) -> { a + b }").unwrap();
+        assert_eq!(run("5 |> add(2)").unwrap(), "7");
+        assert_eq!(run("inc = add(1)\n5 |> inc()").unwrap(), "6");
+        assert_eq!(run("1 + 2 |> inc").unwrap(), "4");
+        assert_eq!(
+            run("[1, 2, 3, 4] |> filter((x, i) -> { x % 2 == 0 }) |> map((x, i) -> { x * 10 })")
+                .unwrap(),
+            "[20, 40]"
+        );
+        assert_eq!(run("[1, 2] |> len() + 1").unwrap(), "3");
+        assert_eq!(run("g = none\n1 |> g?.()").unwrap(), "none");
+    }
+
+    #[test]
+    fn format_specifiers() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        run("x = 3.14159\nn = 1234567").unwrap();
+        assert_eq!(
+            run("\"${x:.2f} ${n:,} ${-42:+06}\"").unwrap(),
+            "3.14 1,234,567 -00042"
+        );
+        assert_eq!(
+            run("\"${255:#x} ${5:04b} ${255:#06X}\"").unwrap(),
+            "0xff 0101 0x00FF"
+        );
+        assert_eq!(
+            run("\"[${\"ab\":>4}|${\"ab\":*^6}|${n:<9}]\"").unwrap(),
+            "[  ab|**ab**|1234567  ]"
+        );
+        assert_eq!(
+            run("format(\"{:>5}|{0:.1f}|{{}}\", 2)").unwrap(),
+            "    2|2.0|{}"
+        );
+        assert_eq!(run("z = -0.0\n\"${z:+.1f}\"").unwrap(), "-0.0");
+        assert!(run("format(\"{} {}\", 1)").is_err());
+        let err = run("format(\"{}\", 1, 2)").unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::InvalidParameterCount(3, 2))
+        ));
+        assert!(run("\"${\"text\":+}\"").is_err());
+    }
+
+    #[test]
+    fn raw_strings_and_heredocs() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        assert_eq!(run(r#"r"C:\new\${x}""#).unwrap(), r"C:\new\${x}");
+        assert_eq!(run(r###"r#"say "hi""#"###).unwrap(), r#"say "hi""#);
+        run("name = \"web\"").unwrap();
+        assert_eq!(
+            run("\"\"\"\n    [${name}]\n      port = 80\n    \"\"\"").unwrap(),
+            "[web]\n  port = 80"
+        );
+        assert_eq!(
+            run("r\"\"\"\n  ${name}\n  \\n\"\"\"").unwrap(),
+            "${name}\n\\n"
+        );
+    }
+
+    #[test]
+    fn declarations() {
+        let mut runtime = Runtime::new();
+        let mut run = runner(&mut runtime);
+        run("i = 100\nhelper = () -> {\nlet i = 0\ni = i + 5\n}").unwrap();
+        assert_eq!(run("helper()\ni").unwrap(), "100");
+        run("const LIMIT = 3").unwrap();
+        assert!(run("LIMIT = 4").is_err());
+        assert!(run("LIMIT += 1").is_err());
+        assert!(run("let LIMIT = 4").is_err());
+        assert_eq!(run("{\nlet LIMIT = 4\nLIMIT\n}").unwrap(), "4");
+        assert_eq!(run("let [a, b] = [1, 2]\na + b").unwrap(), "3");
+        // parameters, rest parameters and loop variables bind like `let`
+        run("x = 100\nparts = 1").unwrap();
+        assert_eq!(run("for x in [1, 2] { x }\nx").unwrap(), "100");
+        assert_eq!(
+            run("g = (x, ...parts) -> {\nx = x + 1\nx\n}\ng(1, 2)").unwrap(),
+            "2"
+        );
+        assert_eq!(run("\"${x} ${parts}\"").unwrap(), "100 1");
+        // other assignments still write the variable of the enclosing scope
+        assert_eq!(
+            run("total = 0\nfor n in [1, 2] { total += n }\ntotal").unwrap(),
+            "3"
+        );
+
+        let mut strict = Runtime::builder().strict(true).build();
+        let mut run = runner(&mut strict);
+        let err = run("x = 1").unwrap_err();
+        assert!(matches!(
+            CashError::find(&*err),
+            Some(CashError::UndeclaredVariable(_))
+        ));
+        assert_eq!(run("let x = 1\nx = x + 1").unwrap(), "2");
+        assert_eq!(run("let f = (n) -> { n * 2 }\nf(x)").unwrap(), "4");
+        assert!(run("a, b = 1, 2").is_err());
+    }
 }
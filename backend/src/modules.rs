@@ -0,0 +1,102 @@
+use crate::context::LockableContext;
+use crate::error::CashError;
+use crate::program::Program;
+use crate::value::{Value, ValueResult};
+use crate::values::DictValue;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Extension, which may be left out in imports
+const EXTENSION: &str = "cash";
+
+/// Imported modules of a runtime, every file is evaluated only once
+#[derive(Debug, Default)]
+pub struct Modules {
+    /// directories searched for imports which are not relative, e.g. `import "ui"`
+    pub search_path: Vec<PathBuf>,
+    loaded: HashMap<PathBuf, Box<dyn Value>>,
+}
+
+impl Modules {
+    pub fn new(search_path: Vec<PathBuf>) -> Self {
+        Modules {
+            search_path,
+            ..Default::default()
+        }
+    }
+
+    /// The directories listed in `CASH_PATH`
+    pub fn search_path_from_env() -> Vec<PathBuf> {
+        match std::env::var_os("CASH_PATH") {
+            Some(paths) => std::env::split_paths(&paths).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Relative paths (`./`, `../`) are resolved from `base`,
+    /// other paths from `base` first and the search path afterwards
+    pub fn resolve(&self, path: &str, base: &Path) -> Option<PathBuf> {
+        let relative = path.starts_with("./") || path.starts_with("../");
+        let mut candidates = vec![base.join(path)];
+        if !relative {
+            candidates.extend(
+                self.search_path
+                    .iter()
+                    .map(|directory| directory.join(path)),
+            );
+        }
+        candidates
+            .into_iter()
+            .flat_map(|candidate| {
+                let with_extension = candidate.with_extension(EXTENSION);
+                vec![candidate, with_extension]
+            })
+            .find(|candidate| candidate.is_file())
+            .and_then(|candidate| std::fs::canonicalize(candidate).ok())
+    }
+}
+
+/// Evaluates the file at `path` in its own context and returns its globals as a dict
+/// `path` has to be canonical
+/// Cycles are found through the imports leading to `ctx`, so concurrent imports of a module do not collide
+pub fn import(path: PathBuf, ctx: LockableContext) -> ValueResult {
+    let (modules, imports) = {
+        let lock = ctx.read();
+        (lock.modules.clone(), lock.imports.clone())
+    };
+    if let Some(module) = modules.read().loaded.get(&path) {
+        return Ok((*module).clone());
+    }
+    if let Some(position) = imports.iter().position(|loading| *loading == path) {
+        let cycle: Vec<String> = imports[position..]
+            .iter()
+            .chain(std::iter::once(&path))
+            .map(|path| path.display().to_string())
+            .collect();
+        return CashError::ImportCycle(cycle.join(" -> ")).boxed();
+    }
+    let mut chain = (*imports).clone();
+    chain.push(path.clone());
+
+    let module = evaluate(&path, ctx, chain)?;
+    modules.write().loaded.insert(path, module.clone());
+    Ok(module)
+}
+
+fn evaluate(path: &Path, ctx: LockableContext, imports: Vec<PathBuf>) -> ValueResult {
+    let text = std::fs::read_to_string(path)?;
+    let program = match Program::compile(&text, &path.display().to_string()) {
+        Ok(program) => program,
+        Err(diagnostics) => return CashError::ParseError(diagnostics.to_string()).boxed(),
+    };
+    let module = ctx.read().isolated();
+    module.write().imports = Arc::new(imports);
+    program.eval(module.clone())?;
+    let globals = module
+        .read()
+        .vars()
+        .map(|(name, value)| (name.clone(), (*value).clone()))
+        .collect();
+    DictValue::boxed(globals)
+}
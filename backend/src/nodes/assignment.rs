@@ -3,10 +3,11 @@ use crate::context::LockableContext;
 use crate::error::CashError;
 use crate::rules::Rule;
 use crate::value::ValueResult;
+use crate::values::ListValue;
 use pest::iterators::Pairs;
 use std::sync::Arc;
 
-use super::{Expr, Infix, Postfix};
+use super::{Expr, Infix, Pattern, Postfix};
 
 #[derive(Debug)]
 pub struct Assignment {
@@ -18,6 +19,10 @@ pub struct Assignment {
 
 impl Node for Assignment {
     fn eval(&self, ctx: LockableContext) -> ValueResult {
+        if self.ident.starts_with('$') {
+            ctx.read().permissions.check_env_write(&self.ident)?;
+        }
+        ctx.read().check_assign(&self.ident)?;
         let mut result = self.expr.eval(ctx.clone())?;
         if let Some(infix) = &self.infix {
             if let Some(mut val) = ctx.read().get(&self.ident) {
@@ -45,7 +50,7 @@ impl Node for Assignment {
         }
         if self.indexes.is_empty() {
             // only place that will ever write to the context
-            ctx.write().set(&self.ident, (*result).clone());
+            ctx.write().set(&self.ident, (*result).clone())?;
             Ok(result)
         } else {
             let mut indexes = Vec::with_capacity(self.indexes.len());
@@ -62,7 +67,7 @@ impl Node for Assignment {
             let mut lock = ctx.write();
             if let Some(mut val) = lock.get(&self.ident) {
                 val.indexed_set((*result).clone(), &indexes)?;
-                lock.set(&self.ident, val);
+                lock.set(&self.ident, val)?;
                 Ok(result)
             } else {
                 CashError::VariableNotFound(self.ident.clone()).boxed()
@@ -86,7 +91,7 @@ impl Assignment {
         let mut expr = None;
         for pair in pairs {
             match pair.as_rule() {
-                Rule::Indexing => indexes.push(Postfix::parse(pair)),
+                Rule::Indexing => indexes.push(Postfix::parse(pair)?),
                 Rule::Infix => infix = Some(Infix::parse(pair)),
                 Rule::Expr => {
                     expr = Some(make_ast(pair)?);
@@ -123,3 +128,89 @@ impl std::fmt::Display for Assignment {
         write!(f, "{}", s)
     }
 }
+
+/// `[a, b, ..rest] = list`, `{host, port} = cfg` or `a, b = b, a`
+#[derive(Debug)]
+pub struct Destructuring {
+    pub pattern: Pattern,
+    /// several expressions are assigned as a list, all of them are evaluated first
+    pub exprs: Vec<Arc<dyn Node>>,
+}
+
+impl Node for Destructuring {
+    fn eval(&self, ctx: LockableContext) -> ValueResult {
+        let value = if self.exprs.len() == 1 {
+            self.exprs[0].eval(ctx.clone())?
+        } else {
+            let mut values = Vec::with_capacity(self.exprs.len());
+            for expr in &self.exprs {
+                values.push(expr.eval(ctx.clone())?);
+            }
+            ListValue::boxed(values)?
+        };
+        self.pattern.assign(&*value, &ctx)?;
+        Ok(value)
+    }
+}
+
+impl Destructuring {
+    pub fn parse_inner(
+        mut pairs: Pairs<Rule>,
+    ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+        let pattern = Pattern::parse_inner(pairs.next().expect("due to grammar.pest"))?;
+        let mut exprs = Vec::new();
+        for pair in pairs {
+            exprs.push(make_ast(pair)?);
+        }
+        Ok(Arc::new(Destructuring { pattern, exprs }))
+    }
+}
+
+impl std::fmt::Display for Destructuring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Destructuring {:?}", self.pattern)
+    }
+}
+
+/// `let name = value` binds in the current block, even if an outer block has the same variable
+/// `const name = value` can not be assigned to afterwards
+#[derive(Debug)]
+pub struct Declaration {
+    pub pattern: Pattern,
+    pub constant: bool,
+    pub expr: Arc<dyn Node>,
+}
+
+impl Node for Declaration {
+    fn eval(&self, ctx: LockableContext) -> ValueResult {
+        let value = self.expr.eval(ctx.clone())?;
+        self.pattern.declare(&*value, &ctx, self.constant)?;
+        Ok(value)
+    }
+}
+
+impl Declaration {
+    pub fn parse_inner(
+        mut pairs: Pairs<Rule>,
+    ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+        let constant = pairs.next().expect("due to grammar.pest").as_rule() == Rule::Const;
+        let pattern = Pattern::parse_inner(pairs.next().expect("due to grammar.pest"))?;
+        let expr = make_ast(pairs.next().expect("due to grammar.pest"))?;
+        Ok(Arc::new(Declaration {
+            pattern,
+            constant,
+            expr,
+        }))
+    }
+}
+
+impl std::fmt::Display for Declaration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keyword = if self.constant { "const" } else { "let" };
+        write!(
+            f,
+            "Declaration {} {:?} = {}",
+            keyword, self.pattern, self.expr
+        )
+    }
+}
@@ -0,0 +1,176 @@
+use crate::ast::*;
+use crate::context::Context;
+use crate::context::LockableContext;
+use crate::error::CashError;
+use crate::rules::Rule;
+use crate::value::{Value, ValueResult};
+use crate::values::{BooleanValue, DictValue, IteratorValue, ListValue, StringValue};
+use pest::iterators::{Pair, Pairs};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::Pattern;
+
+/// `for pattern in iterable if condition`
+#[derive(Debug)]
+pub struct ComprehensionClause {
+    pub pattern: Pattern,
+    /// `for key, value in dict`, iterates the entries of dicts instead of the keys
+    pub entries: bool,
+    pub iterable: Arc<dyn Node>,
+    pub condition: Option<Arc<dyn Node>>,
+}
+
+impl ComprehensionClause {
+    fn parse(pair: Pair<Rule>) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let mut inner = pair.into_inner();
+        let target = inner.next().unwrap();
+        let entries = target.as_rule() == Rule::TuplePattern;
+        let pattern = if entries {
+            Pattern::parse_inner(target)?
+        } else {
+            Pattern::parse(target)?
+        };
+        let iterable = make_ast(inner.next().unwrap())?;
+        let condition = match inner.next() {
+            Some(condition) => Some(make_ast(condition.into_inner().next().unwrap())?),
+            None => None,
+        };
+        Ok(ComprehensionClause {
+            pattern,
+            entries,
+            iterable,
+            condition,
+        })
+    }
+
+    fn iter(
+        &self,
+        ctx: LockableContext,
+    ) -> Result<IteratorValue, Box<dyn std::error::Error + Sync + Send>> {
+        let iterable = self.iterable.eval(ctx)?;
+        if self.entries {
+            if let Some(dict) = iterable.downcast_ref::<DictValue>() {
+                let mut entries = Vec::with_capacity(dict.values.len());
+                for (key, value) in &dict.values {
+                    entries.push(ListValue::boxed(vec![
+                        StringValue::boxed(key.clone())?,
+                        (**value).clone(),
+                    ]));
+                }
+                return Ok(IteratorValue::new(entries.into_iter()));
+            }
+        }
+        iterable.iter()
+    }
+}
+
+#[derive(Debug)]
+pub enum ComprehensionKind {
+    /// `[value for ...]`
+    List(Arc<dyn Node>),
+    /// `{key: value for ...}`
+    Dict(Arc<dyn Node>, Arc<dyn Node>),
+}
+
+/// `[x * 2 for x in xs if x > 0]` or `{k: v for k, v in dict}`
+/// Every clause iterates inside of the one before it, the variables are bound in child contexts
+#[derive(Debug)]
+pub struct Comprehension {
+    pub kind: ComprehensionKind,
+    pub clauses: Vec<ComprehensionClause>,
+}
+
+impl Comprehension {
+    /// Calls `emit` with a context for every combination of items, which satisfies the conditions
+    fn each(
+        &self,
+        clauses: &[ComprehensionClause],
+        ctx: LockableContext,
+        emit: &mut dyn FnMut(
+            LockableContext,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>>,
+    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        let (clause, rest) = match clauses.split_first() {
+            Some(split) => split,
+            None => return emit(ctx),
+        };
+        for value in clause.iter(ctx.clone())? {
+            let value = value?;
+            let ctx = Context::from_parent(ctx.clone());
+            clause.pattern.assign_local(&*value, &ctx)?;
+            if let Some(condition) = &clause.condition {
+                let val = condition.eval(ctx.clone())?;
+                match val.downcast_ref::<BooleanValue>() {
+                    Some(val) if !val.value => continue,
+                    Some(_) => {}
+                    None => {
+                        return CashError::InvalidType(
+                            val.get_type_name().to_owned(),
+                            "condition".to_owned(),
+                        )
+                        .boxed()
+                    }
+                }
+            }
+            self.each(rest, ctx, emit)?;
+        }
+        Ok(())
+    }
+}
+
+impl Node for Comprehension {
+    fn eval(&self, ctx: LockableContext) -> ValueResult {
+        let ctx = Context::from_parent(ctx);
+        match &self.kind {
+            ComprehensionKind::List(value) => {
+                let mut values: Vec<Box<dyn Value>> = Vec::new();
+                self.each(&self.clauses, ctx, &mut |ctx| {
+                    values.push(value.eval(ctx)?);
+                    Ok(())
+                })?;
+                ListValue::boxed(values)
+            }
+            ComprehensionKind::Dict(key, value) => {
+                let mut values: HashMap<String, Box<dyn Value>> = HashMap::new();
+                self.each(&self.clauses, ctx, &mut |ctx| {
+                    let key = key.eval(ctx.clone())?.to_string();
+                    values.insert(key, value.eval(ctx)?);
+                    Ok(())
+                })?;
+                DictValue::boxed(values)
+            }
+        }
+    }
+}
+
+impl Comprehension {
+    pub fn parse_inner(
+        mut pairs: Pairs<Rule>,
+        dict: bool,
+    ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+        let first = make_ast(pairs.next().expect("due to grammar.pest"))?;
+        let kind = if dict {
+            let value = make_ast(pairs.next().expect("due to grammar.pest"))?;
+            ComprehensionKind::Dict(first, value)
+        } else {
+            ComprehensionKind::List(first)
+        };
+        let mut clauses = Vec::new();
+        for pair in pairs {
+            clauses.push(ComprehensionClause::parse(pair)?);
+        }
+        Ok(Arc::new(Self { kind, clauses }))
+    }
+}
+
+impl std::fmt::Display for Comprehension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ComprehensionKind::List(value) => write!(f, "ListComprehension '{}'", value),
+            ComprehensionKind::Dict(key, value) => {
+                write!(f, "DictComprehension '{}: {}'", key, value)
+            }
+        }
+    }
+}
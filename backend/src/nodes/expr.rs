@@ -1,11 +1,13 @@
-use super::literals::StringLiteral;
+use super::literals::{NoneLiteral, StringLiteral};
 use crate::ast::*;
-use crate::context::LockableContext;
+use crate::context::{Context, LockableContext};
 use crate::error::CashError;
 use crate::rules::Rule;
 use crate::value::{Value, ValueResult};
-use crate::values::FutureValue;
+use crate::values::{BooleanValue, DictValue, FutureValue, NoneValue, RangeValue};
+use parking_lot::const_rwlock;
 use pest::iterators::{Pair, Pairs};
+use std::collections::HashMap;
 use std::iter::Peekable;
 use std::slice::Iter;
 use std::sync::Arc;
@@ -27,45 +29,84 @@ impl Node for Expr {
                 infixes: self.infixes.clone(),
                 is_async: false,
             };
-            FutureValue::boxed(Arc::new(new_node), ctx)
+            // a task may change its working directory without affecting the caller
+            let task = Context::from_parent(ctx);
+            let cwd = task.read().get_cwd();
+            task.write().cwd = Arc::new(const_rwlock(cwd));
+            FutureValue::boxed(Arc::new(new_node), task)
         } else {
-            let mut values = Vec::new();
-            for value in &self.values {
-                values.push(self.eval_primary(&value, ctx.clone())?);
-            }
-            Self::climb_ops(
-                &mut values.into_iter(),
+            self.climb_ops(
+                &mut self.values.iter(),
                 &mut self.infixes.iter().peekable(),
                 0,
+                &ctx,
+                true,
             )
         }
     }
 }
 
 impl Expr {
+    /// Operands are only evaluated when they are needed, so `&&`, `||` and `??` can skip them
     fn climb_ops(
-        values: &mut impl std::iter::Iterator<Item = Box<dyn Value>>,
+        &self,
+        values: &mut Iter<Primary>,
         infixes: &mut Peekable<Iter<Infix>>,
         min_precedence: usize,
+        ctx: &LockableContext,
+        evaluate: bool,
     ) -> ValueResult {
-        let mut result = values.next().expect("A value should exist");
+        let primary = values.next().expect("A value should exist");
+        let mut result = if evaluate {
+            self.eval_primary(primary, ctx.clone(), None)?
+        } else {
+            NoneValue::boxed()?
+        };
         while let Some(next) = infixes.peek() {
             let mut prec = next.precedence();
             if prec < min_precedence {
                 break;
             }
             let next = infixes.next().expect("Peeked, so should have next");
+            if let Infix::Pipe = next {
+                // the right-hand side is a single call, which receives `result` first
+                let primary = values.next().expect("A value should exist");
+                if evaluate {
+                    result = self.eval_primary(primary, ctx.clone(), Some(result))?;
+                }
+                continue;
+            }
             if next.left_to_right() {
                 prec += 1;
             }
-            let rhs = Self::climb_ops(values, infixes, prec)?;
-            result = Self::compute_infix(result, &rhs, next)?;
+            let skip = !evaluate || next.short_circuits(&*result);
+            let rhs = self.climb_ops(values, infixes, prec, ctx, !skip)?;
+            if !skip {
+                result = Self::compute_infix(result, &rhs, next)?;
+            }
         }
         Ok(result)
     }
 
     pub fn compute_infix(lhs: Box<dyn Value>, rhs: &Box<dyn Value>, infix: &Infix) -> ValueResult {
         match infix {
+            Infix::Pipe => {
+                CashError::InvalidOperation("|>".to_owned(), "assignment".to_owned()).boxed()
+            }
+            Infix::Range | Infix::RangeInclusive => {
+                fn bound(value: &dyn Value) -> Option<&dyn Value> {
+                    Some(value).filter(|value| value.get_type_name() != "none")
+                }
+                let inclusive = matches!(infix, Infix::RangeInclusive);
+                RangeValue::from_bounds(bound(&*lhs), bound(&**rhs), inclusive)
+            }
+            Infix::Step => match lhs.downcast::<RangeValue>() {
+                Ok(range) => range.with_step(&**rhs),
+                Err(lhs) => {
+                    CashError::InvalidOperation("step".to_owned(), lhs.get_type_name().to_owned())
+                        .boxed()
+                }
+            },
             Infix::Exponentiation => lhs.power(rhs),
             Infix::Multiply => lhs.multiply(rhs),
             Infix::Divide => lhs.division(rhs),
@@ -84,31 +125,79 @@ impl Expr {
             Infix::And => lhs.and(rhs),
             Infix::Xor => lhs.xor(rhs),
             Infix::Or => lhs.or(rhs),
+            Infix::LogicalAnd | Infix::LogicalOr => {
+                // unlike `&` and `|`, only booleans are allowed
+                let and = matches!(infix, Infix::LogicalAnd);
+                let operator = if and { "&&" } else { "||" };
+                let boolean = |value: &dyn Value| match value.downcast_ref::<BooleanValue>() {
+                    Some(boolean) => Ok(boolean.value),
+                    None => CashError::InvalidType(
+                        value.get_type_name().to_owned(),
+                        operator.to_owned(),
+                    )
+                    .boxed(),
+                };
+                let (lhs, rhs) = (boolean(&*lhs)?, boolean(&**rhs)?);
+                BooleanValue::boxed(if and { lhs && rhs } else { lhs || rhs })
+            }
+            Infix::Coalesce => {
+                if lhs.get_type_name() == "none" {
+                    Ok((**rhs).clone())
+                } else {
+                    Ok(lhs)
+                }
+            }
         }
     }
 
+    /// `piped` is passed as the first argument of the first call,
+    /// the value is called with it if there is no call
     fn eval_primary(
         &self,
         primary: &(Vec<Prefix>, Arc<dyn Node>, Vec<Postfix>),
         ctx: LockableContext,
+        mut piped: Option<Box<dyn Value>>,
     ) -> ValueResult {
         let (prefixes, value, postfixes) = primary;
         let mut value = value.eval(ctx.clone())?;
         for postfix in postfixes {
+            let safe = matches!(
+                postfix,
+                Postfix::SafeFunctionCall(_) | Postfix::SafeIndexing(_)
+            );
+            if safe && value.get_type_name() == "none" {
+                // skips the rest of the chain
+                piped = None;
+                break;
+            }
             match postfix {
-                Postfix::FunctionCall(nodes) => {
-                    let mut args: Vec<Box<dyn Value>> = Vec::new();
-                    for node in nodes {
-                        args.push(node.eval(ctx.clone())?);
-                    }
-                    value = value.call(args, ctx.clone())?;
+                Postfix::FunctionCall(arguments) => {
+                    value = Argument::call(&*value, piped.take(), arguments, ctx.clone())?;
                 }
                 Postfix::Indexing(node) => {
                     let arg: Box<dyn Value> = node.eval(ctx.clone())?;
                     value = value.index(&arg)?;
                 }
+                Postfix::SafeFunctionCall(arguments) => {
+                    value = Argument::call(&*value, piped.take(), arguments, ctx.clone())?;
+                }
+                Postfix::SafeIndexing(node) => {
+                    let arg: Box<dyn Value> = node.eval(ctx.clone())?;
+                    let missing = match value.downcast_ref::<DictValue>() {
+                        Some(dict) => !dict.values.contains_key(&arg.to_string()),
+                        None => false,
+                    };
+                    value = if missing {
+                        NoneValue::boxed()?
+                    } else {
+                        value.index(&arg)?
+                    };
+                }
             }
         }
+        if let Some(piped) = piped {
+            value = value.call(vec![piped], ctx.clone())?;
+        }
         for prefix in prefixes.iter().rev() {
             match prefix {
                 Prefix::UnaryPlus => {
@@ -167,7 +256,12 @@ impl Expr {
                 Rule::Prefix => {
                     prefixes.push(Prefix::parse(pair));
                 }
-                Rule::Expr | Rule::Capture | Rule::Block => {
+                Rule::Expr
+                | Rule::Capture
+                | Rule::Import
+                | Rule::Match
+                | Rule::Try
+                | Rule::Block => {
                     primary = Some(make_ast(pair)?);
                 }
                 Rule::Literal => {
@@ -182,14 +276,29 @@ impl Expr {
                         pair.into_inner()
                             .next()
                             .expect("Did not find node in postfix?"),
+                    )?);
+                }
+                Rule::RangeTo => {
+                    // `..upper` is `none..upper`
+                    values.push((
+                        Vec::new(),
+                        Arc::new(NoneLiteral) as Arc<dyn Node>,
+                        Vec::new(),
                     ));
+                    infixes.push(Infix::parse(pair));
                 }
-                Rule::Infix => {
+                Rule::Infix | Rule::RangeFrom => {
+                    let open = pair.as_rule() == Rule::RangeFrom;
                     infixes.push(Infix::parse(pair));
                     values.push((prefixes, primary.expect("Primary should exist"), postfixes));
                     prefixes = Vec::new();
                     postfixes = Vec::new();
-                    primary = None;
+                    // `lower..` is `lower..none`
+                    primary = if open {
+                        Some(Arc::new(NoneLiteral))
+                    } else {
+                        None
+                    };
                 }
                 Rule::Ident => {
                     primary = Some(make_ast(pair)?);
@@ -231,6 +340,14 @@ impl Prefix {
 
 #[derive(Debug, Clone)]
 pub enum Infix {
+    /// `value |> f(a)` is `f(value, a)`
+    Pipe,
+    /// `..`, either bound may be `none` for an open range
+    Range,
+    /// `..=`
+    RangeInclusive,
+    /// `range step n`
+    Step,
     Exponentiation,
     Multiply,
     Divide,
@@ -249,11 +366,19 @@ pub enum Infix {
     And,
     Xor,
     Or,
+    LogicalAnd,
+    LogicalOr,
+    Coalesce,
 }
 
 impl Infix {
     pub fn parse(pair: Pair<Rule>) -> Self {
-        match pair.as_span().as_str() {
+        // the lookahead after `..` may leave whitespace in the span
+        match pair.as_span().as_str().trim_end() {
+            "|>" => Self::Pipe,
+            ".." => Self::Range,
+            "..=" => Self::RangeInclusive,
+            "step" => Self::Step,
             "**" => Self::Exponentiation,
             "*" => Self::Multiply,
             "/" => Self::Divide,
@@ -272,34 +397,60 @@ impl Infix {
             "&" => Self::And,
             "^" => Self::Xor,
             "|" => Self::Or,
+            "&&" => Self::LogicalAnd,
+            "||" => Self::LogicalOr,
+            "??" => Self::Coalesce,
             _ => panic!("Unrecognized infix operator"),
         }
     }
 
     pub fn precedence(&self) -> usize {
         match self {
-            Self::Exponentiation => 9,
-            Self::Multiply => 8,
-            Self::Divide => 8,
-            Self::Modulo => 8,
-            Self::Add => 7,
-            Self::Subtract => 7,
-            Self::BitShiftLeft => 6,
-            Self::BitShiftRight => 6,
-            Self::In => 5,
-            Self::Lt => 5,
-            Self::Gt => 5,
-            Self::Lte => 5,
-            Self::Gte => 5,
-            Self::Ne => 4,
-            Self::Equality => 4,
-            Self::And => 3,
-            Self::Xor => 2,
-            Self::Or => 1,
+            Self::Exponentiation => 14,
+            Self::Multiply => 13,
+            Self::Divide => 13,
+            Self::Modulo => 13,
+            Self::Add => 12,
+            Self::Subtract => 12,
+            Self::BitShiftLeft => 11,
+            Self::BitShiftRight => 11,
+            Self::Pipe => 0,
+            Self::Range => 10,
+            Self::RangeInclusive => 10,
+            Self::Step => 9,
+            Self::In => 8,
+            Self::Lt => 8,
+            Self::Gt => 8,
+            Self::Lte => 8,
+            Self::Gte => 8,
+            Self::Ne => 7,
+            Self::Equality => 7,
+            Self::And => 6,
+            Self::Xor => 5,
+            Self::Or => 4,
+            Self::LogicalAnd => 3,
+            Self::LogicalOr => 2,
+            Self::Coalesce => 1,
+        }
+    }
+
+    /// Whether the right-hand side is not needed, the result is the left-hand side then
+    pub fn short_circuits(&self, lhs: &dyn Value) -> bool {
+        match self {
+            Self::LogicalAnd => {
+                matches!(lhs.downcast_ref::<BooleanValue>(), Some(lhs) if !lhs.value)
+            }
+            Self::LogicalOr => matches!(lhs.downcast_ref::<BooleanValue>(), Some(lhs) if lhs.value),
+            Self::Coalesce => lhs.get_type_name() != "none",
+            _ => false,
         }
     }
     pub fn left_to_right(&self) -> bool {
         match self {
+            Self::Pipe => true,
+            Self::Range => true,
+            Self::RangeInclusive => true,
+            Self::Step => true,
             Self::Exponentiation => false,
             Self::Multiply => true,
             Self::Divide => true,
@@ -318,42 +469,130 @@ impl Infix {
             Self::And => true,
             Self::Xor => true,
             Self::Or => true,
+            Self::LogicalAnd => true,
+            Self::LogicalOr => true,
+            Self::Coalesce => true,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Postfix {
-    FunctionCall(Vec<Arc<dyn Node>>),
+    FunctionCall(Vec<Argument>),
     Indexing(Arc<dyn Node>),
+    /// `?.()`, skipped if the value is `none`
+    SafeFunctionCall(Vec<Argument>),
+    /// `?.[]` and `?::`, also `none` if a dict does not contain the key
+    SafeIndexing(Arc<dyn Node>),
 }
 
 impl Postfix {
-    pub fn parse(inner: Pair<Rule>) -> Self {
-        match inner.as_rule() {
-            Rule::FunctionCall => {
-                let mut nodes = Vec::new();
-                for node in inner.into_inner() {
-                    nodes.push(make_ast(node).expect("Could not create node from function call"));
-                }
-                Self::FunctionCall(nodes)
-            }
-            Rule::Indexing => {
-                let node = inner
+    pub fn parse(inner: Pair<Rule>) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(match inner.as_rule() {
+            Rule::FunctionCall => Self::FunctionCall(Self::parse_arguments(inner)?),
+            Rule::Indexing => Self::Indexing(Self::parse_index(inner)?),
+            Rule::SafeCall => Self::SafeFunctionCall(Self::parse_arguments(
+                inner
                     .into_inner()
                     .next()
-                    .expect("Indexing should contain a node");
-                if node.as_rule() == Rule::Ident {
-                    Self::Indexing(Arc::new(StringLiteral {
-                        strings: vec![node.as_span().as_str().to_owned()],
-                        interpolations: Vec::new(),
-                    }))
-                } else {
-                    let node = make_ast(node).expect("Could not create node from indexing");
-                    Self::Indexing(node)
+                    .expect("SafeCall should contain a function call"),
+            )?),
+            Rule::SafeIndexing => Self::SafeIndexing(Self::parse_index(inner)?),
+            _ => panic!("Should not contain other rule"),
+        })
+    }
+
+    fn parse_arguments(
+        inner: Pair<Rule>,
+    ) -> Result<Vec<Argument>, Box<dyn std::error::Error + Sync + Send>> {
+        inner.into_inner().map(Argument::parse).collect()
+    }
+
+    fn parse_index(
+        inner: Pair<Rule>,
+    ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+        let node = inner
+            .into_inner()
+            .next()
+            .expect("Indexing should contain a node");
+        if node.as_rule() == Rule::Ident {
+            Ok(Arc::new(StringLiteral {
+                strings: vec![node.as_span().as_str().to_owned()],
+                interpolations: Vec::new(),
+            }))
+        } else {
+            make_ast(node)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Argument {
+    Positional(Arc<dyn Node>),
+    /// `name = value`
+    Named(String, Arc<dyn Node>),
+    /// `...list`, the items are passed as positional arguments
+    Spread(Arc<dyn Node>),
+    /// `**dict`, the entries are passed as named arguments
+    SpreadNamed(Arc<dyn Node>),
+}
+
+impl Argument {
+    pub fn parse(pair: Pair<Rule>) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(match pair.as_rule() {
+            Rule::NamedArgument => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_span().as_str().to_owned();
+                Argument::Named(name, make_ast(inner.next().unwrap())?)
+            }
+            Rule::Spread => Argument::Spread(make_ast(pair.into_inner().next().unwrap())?),
+            Rule::SpreadNamed => {
+                Argument::SpreadNamed(make_ast(pair.into_inner().next().unwrap())?)
+            }
+            _ => Argument::Positional(make_ast(pair)?),
+        })
+    }
+
+    /// Evaluates the arguments from left to right and calls `function` with them
+    /// `first` is passed before the arguments, e.g. the left-hand side of `|>`
+    pub fn call(
+        function: &dyn Value,
+        first: Option<Box<dyn Value>>,
+        arguments: &[Argument],
+        ctx: LockableContext,
+    ) -> ValueResult {
+        let mut args: Vec<Box<dyn Value>> = first.into_iter().collect();
+        let mut named = HashMap::new();
+        for argument in arguments {
+            match argument {
+                Argument::Positional(node) => args.push(node.eval(ctx.clone())?),
+                Argument::Named(name, node) => {
+                    let value = node.eval(ctx.clone())?;
+                    if named.insert(name.clone(), value).is_some() {
+                        return CashError::DuplicateArgument(name.clone()).boxed();
+                    }
+                }
+                Argument::Spread(node) => args.extend(node.eval(ctx.clone())?.vec()?),
+                Argument::SpreadNamed(node) => {
+                    let value = node.eval(ctx.clone())?;
+                    let dict = match value.downcast::<DictValue>() {
+                        Ok(dict) => dict,
+                        Err(value) => {
+                            return CashError::InvalidType(
+                                value.get_type_name().to_owned(),
+                                "named arguments".to_owned(),
+                            )
+                            .boxed()
+                        }
+                    };
+                    for (name, value) in dict.values {
+                        if named.insert(name.clone(), value).is_some() {
+                            return CashError::DuplicateArgument(name).boxed();
+                        }
+                    }
                 }
             }
-            _ => panic!("Should not contain other rule"),
         }
+        function.call_named(args, named, ctx)
     }
 }
@@ -13,7 +13,11 @@ pub struct Ident {
 
 impl Node for Ident {
     fn eval(&self, ctx: LockableContext) -> ValueResult {
-        if let Some(val) = ctx.read().get(&self.ident) {
+        let ctx = ctx.read();
+        if self.ident.starts_with('$') {
+            ctx.permissions.check_env_read(&self.ident)?;
+        }
+        if let Some(val) = ctx.get(&self.ident) {
             Ok(val)
         } else {
             CashError::VariableNotFound(self.ident.clone()).boxed()
@@ -0,0 +1,68 @@
+use crate::ast::*;
+use crate::context::LockableContext;
+use crate::error::CashError;
+use crate::modules;
+use crate::rules::Rule;
+use crate::value::ValueResult;
+use pest::iterators::Pairs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct Import {
+    pub path: Arc<dyn Node>,
+    /// the file containing the import, if it is known
+    pub file: Option<Arc<str>>,
+}
+
+impl Node for Import {
+    fn eval(&self, ctx: LockableContext) -> ValueResult {
+        let path = format!("{}", self.path.eval(ctx.clone())?);
+        let (permissions, modules, cwd) = {
+            let lock = ctx.read();
+            (
+                lock.permissions.clone(),
+                lock.modules.clone(),
+                lock.get_cwd(),
+            )
+        };
+        permissions.check_import(&path)?;
+        let resolved = modules.read().resolve(&path, &self.base_directory(cwd));
+        let resolved = match resolved {
+            Some(resolved) => resolved,
+            None => return CashError::ModuleNotFound(path).boxed(),
+        };
+        permissions.check_path(&resolved)?;
+        modules::import(resolved, ctx)
+    }
+}
+
+impl Import {
+    /// Directory of the importing file or the working directory for scripts without a file
+    fn base_directory(&self, cwd: PathBuf) -> PathBuf {
+        let directory = self
+            .file
+            .as_deref()
+            .map(Path::new)
+            .filter(|file| file.is_file())
+            .and_then(|file| file.parent());
+        match directory {
+            Some(directory) => directory.to_path_buf(),
+            None => cwd,
+        }
+    }
+
+    pub fn parse_inner(
+        mut pairs: Pairs<Rule>,
+        file: Option<Arc<str>>,
+    ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+        let path = make_ast(pairs.next().unwrap())?;
+        Ok(Arc::new(Import { path, file }))
+    }
+}
+
+impl std::fmt::Display for Import {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Import '{}'", self.path)
+    }
+}
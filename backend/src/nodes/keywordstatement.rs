@@ -4,13 +4,17 @@ use crate::error::CashError;
 use crate::nodes::NoneLiteral;
 use crate::rules::Rule;
 use crate::value::ValueResult;
-use crate::values::{BreakValue, ContinueValue, ReturnValue};
+use crate::values::{BreakValue, ContinueValue, ErrorValue, NoneValue, ReturnValue};
 use pest::iterators::Pairs;
 use std::sync::Arc;
 
 #[derive(Debug)]
 pub enum KeywordType {
     Return,
+    /// fails with the value, which is wrapped in an error value if it is not one
+    Throw,
+    /// hands the value to the consumer of the generator
+    Yield,
     Break,
     Continue,
 }
@@ -23,9 +27,35 @@ pub struct KeywordStatement {
 
 impl Node for KeywordStatement {
     fn eval(&self, ctx: LockableContext) -> ValueResult {
-        let val = self.statement.eval(ctx)?;
+        let val = self.statement.eval(ctx.clone())?;
         match self.keyword {
             KeywordType::Return => ReturnValue::boxed(val),
+            KeywordType::Throw => {
+                let error = match val.downcast::<ErrorValue>() {
+                    Ok(error) => error,
+                    Err(val) => Box::new(ErrorValue {
+                        kind: "error".to_owned(),
+                        message: val.to_string(),
+                        data: val,
+                        location: None,
+                    }),
+                };
+                CashError::Thrown(error).boxed()
+            }
+            KeywordType::Yield => {
+                let generator = ctx.read().generator.clone();
+                match generator {
+                    Some(generator) => {
+                        generator.yield_value(val)?;
+                        NoneValue::boxed()
+                    }
+                    None => CashError::InvalidOperation(
+                        "yield".to_owned(),
+                        "code outside of a function".to_owned(),
+                    )
+                    .boxed(),
+                }
+            }
             KeywordType::Break => BreakValue::boxed(val),
             KeywordType::Continue => ContinueValue::boxed(val),
         }
@@ -39,6 +69,8 @@ impl KeywordStatement {
         let first = inner.next().expect("I need to find this");
         let keyword = match first.as_span().as_str() {
             "return" => KeywordType::Return,
+            "throw" => KeywordType::Throw,
+            "yield" => KeywordType::Yield,
             "break" => KeywordType::Break,
             "continue" => KeywordType::Continue,
             a => {
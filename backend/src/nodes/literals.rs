@@ -1,14 +1,17 @@
 use crate::ast::*;
+use crate::cashstd::FormatSpec;
 use crate::context::LockableContext;
 use crate::error::CashError;
 use crate::rules::Rule;
 use crate::value::{Value, ValueResult};
 use crate::values::*;
-use pest::iterators::Pairs;
+use pest::iterators::{Pair, Pairs};
 use std::sync::Arc;
 
 use std::collections::HashMap;
 
+use super::{Expr, Pattern, Prefix};
+
 #[derive(Clone, Debug)]
 pub struct BooleanLiteral {
     pub value: bool,
@@ -93,41 +96,80 @@ impl FloatLiteral {
     }
 }
 
+/// A range in a pattern, e.g. `1..=9` or `-5..`
 #[derive(Debug)]
 pub struct RangeLiteral {
-    lower: Arc<dyn Node>,
-    upper: Arc<dyn Node>,
+    lower: Option<Arc<dyn Node>>,
+    upper: Option<Arc<dyn Node>>,
+    inclusive: bool,
 }
 
 impl Node for RangeLiteral {
     fn eval(&self, ctx: LockableContext) -> ValueResult {
-        let lower = self.lower.eval(ctx.clone())?;
-        let lower = lower.downcast_ref::<IntegerValue>();
-        let upper = self.upper.eval(ctx)?;
-        let upper = upper.downcast_ref::<IntegerValue>();
-        if let (Some(lower), Some(upper)) = (lower, upper) {
-            RangeValue::boxed(lower.value, upper.value)
-        } else {
-            CashError::InvalidType("unknown".to_owned(), "range".to_owned()).boxed()
-        }
+        let lower = match &self.lower {
+            Some(lower) => Some(lower.eval(ctx.clone())?),
+            None => None,
+        };
+        let upper = match &self.upper {
+            Some(upper) => Some(upper.eval(ctx)?),
+            None => None,
+        };
+        RangeValue::from_bounds(lower.as_deref(), upper.as_deref(), self.inclusive)
     }
 }
 impl std::fmt::Display for RangeLiteral {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "RangeLiteral '{}'..'{}'", self.lower, self.upper)
+        write!(f, "RangeLiteral ")?;
+        if let Some(lower) = &self.lower {
+            write!(f, "'{}'", lower)?;
+        }
+        write!(f, "{}", if self.inclusive { "..=" } else { ".." })?;
+        if let Some(upper) = &self.upper {
+            write!(f, "'{}'", upper)?;
+        }
+        Ok(())
     }
 }
 
 impl RangeLiteral {
     pub fn parse_inner(
-        mut pair: Pairs<Rule>,
+        pair: Pairs<Rule>,
     ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
-        let lower_node = pair.next().expect("Could not happen - grammar!");
-        let upper_node = pair.next().expect("Could not happen - grammar!");
-        let lower = make_ast(lower_node)?;
-        let upper = make_ast(upper_node)?;
+        let mut lower = None;
+        let mut upper = None;
+        let mut inclusive = None;
+        for part in pair {
+            match (part.as_rule(), inclusive) {
+                (Rule::RangeOperator, _) => inclusive = Some(part.as_span().as_str() == "..="),
+                (_, None) => lower = Some(Self::parse_bound(part)?),
+                (_, Some(_)) => upper = Some(Self::parse_bound(part)?),
+            }
+        }
+        let inclusive = inclusive.expect("Range should contain an operator");
+        Ok(Arc::new(RangeLiteral {
+            lower,
+            upper,
+            inclusive,
+        }))
+    }
 
-        Ok(Arc::new(RangeLiteral { lower, upper }))
+    fn parse_bound(
+        pair: Pair<Rule>,
+    ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+        let mut parts = pair.into_inner();
+        let first = parts.next().expect("RangeBound should contain a node");
+        if first.as_rule() == Rule::Negative {
+            let number = parts
+                .next()
+                .expect("Negative should be followed by a number");
+            Ok(Arc::new(Expr {
+                values: vec![(vec![Prefix::UnaryMinus], make_ast(number)?, Vec::new())],
+                infixes: Vec::new(),
+                is_async: false,
+            }))
+        } else {
+            make_ast(first)
+        }
     }
 }
 
@@ -218,15 +260,20 @@ impl DictLiteral {
 #[derive(Debug)]
 pub struct StringLiteral {
     pub strings: Vec<String>,
-    pub interpolations: Vec<Arc<dyn Node>>,
+    /// `${value}` or `${value:spec}`
+    pub interpolations: Vec<(Arc<dyn Node>, Option<FormatSpec>)>,
 }
 
 impl Node for StringLiteral {
     fn eval(&self, ctx: LockableContext) -> ValueResult {
         let mut text = self.strings[0].to_owned();
         for i in 0..self.interpolations.len() {
-            let value = self.interpolations[i].eval(ctx.clone())?;
-            text += &value.to_string();
+            let (node, spec) = &self.interpolations[i];
+            let value = node.eval(ctx.clone())?;
+            match spec {
+                Some(spec) => text += &spec.apply(&*value)?,
+                None => text += &value.to_string(),
+            }
             text += &self.strings[i + 1];
         }
         StringValue::boxed(text)
@@ -242,6 +289,29 @@ impl StringLiteral {
     pub fn parse_inner(
         pair: Pairs<Rule>,
     ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(Arc::new(Self::parse_parts(pair, None)?))
+    }
+
+    /// A string without interpolations, e.g. a key of a dict pattern
+    pub fn parse_constant(
+        pair: Pairs<Rule>,
+    ) -> Result<String, Box<dyn std::error::Error + Sync + Send>> {
+        let literal = Self::parse_parts(pair, None)?;
+        if !literal.interpolations.is_empty() {
+            return CashError::InvalidOperation(
+                "interpolation".to_owned(),
+                "constant string".to_owned(),
+            )
+            .boxed();
+        }
+        Ok(literal.strings.concat())
+    }
+
+    /// `indent` is the common indentation of a heredoc, which is removed from every line
+    fn parse_parts(
+        pair: Pairs<Rule>,
+        indent: Option<usize>,
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
         let mut strings = Vec::new();
         let mut interpolations = Vec::new();
         let mut value = String::new();
@@ -259,6 +329,31 @@ impl StringLiteral {
                 Rule::SingleQuoteText | Rule::DoubleQuoteText => {
                     value += node.as_span().as_str();
                 }
+                Rule::HeredocText => {
+                    value += &Self::strip_indent(node.as_span().as_str(), indent.unwrap_or(0));
+                }
+                Rule::RawString => {
+                    let text = node
+                        .into_inner()
+                        .next()
+                        .expect("Could not happen - grammar!");
+                    value += text.as_span().as_str();
+                }
+                Rule::RawHeredoc => {
+                    let text = node
+                        .into_inner()
+                        .next()
+                        .expect("Could not happen - grammar!");
+                    let text = text.as_span().as_str();
+                    let mut strings = vec![Self::strip_indent(text, Self::common_indent(text))];
+                    Self::trim_heredoc(&mut strings);
+                    value += &strings[0];
+                }
+                Rule::Heredoc => {
+                    let text = node.as_span().as_str();
+                    let indent = Self::common_indent(&text[3..text.len() - 3]);
+                    return Self::parse_parts(node.into_inner(), Some(indent));
+                }
                 Rule::Escape => match &node.as_span().as_str()[1..] {
                     "n" => {
                         value += "\n";
@@ -292,14 +387,16 @@ impl StringLiteral {
                     }
                 },
                 Rule::Interpolation => {
-                    let content = node
-                        .into_inner()
-                        .next()
-                        .expect("Could not happen - grammar!");
+                    let mut inner = node.into_inner();
+                    let content = inner.next().expect("Could not happen - grammar!");
                     let result = make_ast(content)?;
+                    let spec = match inner.next() {
+                        Some(spec) => Some(FormatSpec::parse(&spec.as_span().as_str()[1..])?),
+                        None => None,
+                    };
                     strings.push(value);
                     value = String::new();
-                    interpolations.push(result);
+                    interpolations.push((result, spec));
                 }
                 _ => {
                     return CashError::Bug(format!(
@@ -311,17 +408,65 @@ impl StringLiteral {
             }
         }
         strings.push(value);
-        Ok(Arc::new(StringLiteral {
+        if indent.is_some() {
+            Self::trim_heredoc(&mut strings);
+        }
+        Ok(StringLiteral {
             strings,
             interpolations,
-        }))
+        })
+    }
+
+    /// The smallest indentation of the lines after the first one, blank lines are ignored
+    fn common_indent(text: &str) -> usize {
+        text.split('\n')
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Removes up to `indent` spaces or tabs after every newline
+    fn strip_indent(text: &str, indent: usize) -> String {
+        let mut lines = text.split('\n');
+        let mut stripped = lines.next().unwrap_or_default().to_owned();
+        for line in lines {
+            let whitespace = line.len() - line.trim_start_matches([' ', '\t']).len();
+            stripped.push('\n');
+            stripped += &line[whitespace.min(indent)..];
+        }
+        stripped
+    }
+
+    /// Drops the line of the opening and the closing quotes, if they are blank
+    fn trim_heredoc(strings: &mut [String]) {
+        if let Some(first) = strings.first_mut() {
+            if let Some((line, _)) = first.split_once('\n') {
+                if line.trim().is_empty() {
+                    *first = first[line.len() + 1..].to_owned();
+                }
+            }
+        }
+        if let Some(last) = strings.last_mut() {
+            if let Some(position) = last.rfind('\n') {
+                if last[position..].trim().is_empty() {
+                    last.truncate(position);
+                }
+            }
+        }
     }
 }
 
+/// The pattern the argument is bound to and the node of its default value
+pub type ParameterNode = (Arc<Pattern>, Option<Arc<dyn Node>>);
+
 #[derive(Debug)]
 pub struct FunctionLiteral {
     pub node: Arc<dyn Node>,
-    pub params: Vec<(String, Option<Arc<dyn Node>>)>,
+    pub params: Vec<ParameterNode>,
+    pub rest: Option<String>,
+    pub generator: bool,
 }
 
 impl Node for FunctionLiteral {
@@ -334,7 +479,13 @@ impl Node for FunctionLiteral {
             }
             params.push((name.clone(), optional));
         }
-        FunctionValue::boxed(self.node.clone(), ctx, params)
+        FunctionValue::boxed(
+            self.node.clone(),
+            ctx,
+            params,
+            self.rest.clone(),
+            self.generator,
+        )
     }
 }
 impl std::fmt::Display for FunctionLiteral {
@@ -349,12 +500,33 @@ impl FunctionLiteral {
     ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
         let first = pairs.next().unwrap();
         let mut params = Vec::new();
+        let mut rest = None;
         let block;
+        let generator;
         if first.as_rule() == Rule::FunctionParams {
-            block = make_ast(pairs.next().unwrap())?;
+            let pair = pairs.next().unwrap();
+            generator = contains_yield(&pair);
+            block = make_ast(pair)?;
             for param in first.into_inner() {
+                if param.as_rule() == Rule::RestParam {
+                    rest = Some(
+                        param
+                            .into_inner()
+                            .next()
+                            .unwrap()
+                            .as_span()
+                            .as_str()
+                            .to_owned(),
+                    );
+                    continue;
+                }
                 let mut inner = param.into_inner();
-                let name = inner.next().unwrap().as_span().as_str().to_owned();
+                let target = inner.next().unwrap();
+                let pattern = if target.as_rule() == Rule::Ident {
+                    Pattern::Binding(target.as_span().as_str().to_owned())
+                } else {
+                    Pattern::parse_inner(target)?
+                };
                 let default_value = match inner.next() {
                     None => None,
                     Some(value) => Some(match value.as_rule() {
@@ -373,19 +545,33 @@ impl FunctionLiteral {
                         }
                     }),
                 };
-                params.push((name, default_value));
+                params.push((Arc::new(pattern), default_value));
             }
         } else {
+            generator = contains_yield(&first);
             block = make_ast(first)?;
         }
 
         Ok(Arc::new(FunctionLiteral {
             node: block,
             params,
+            rest,
+            generator,
         }))
     }
 }
 
+/// Whether `yield` is used in the body of a function, ignoring nested functions
+fn contains_yield(pair: &Pair<Rule>) -> bool {
+    pair.clone()
+        .into_inner()
+        .any(|inner| match inner.as_rule() {
+            Rule::Yield => true,
+            Rule::Function => false,
+            _ => contains_yield(&inner),
+        })
+}
+
 #[derive(Debug, Default)]
 pub struct NoneLiteral;
 
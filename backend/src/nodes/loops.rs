@@ -6,6 +6,8 @@ use crate::rules::Rule;
 use crate::value::ValueResult;
 use crate::values::{BooleanValue, BreakValue, ContinueValue, NoneValue};
 use pest::iterators::Pairs;
+
+use super::Pattern;
 use std::sync::Arc;
 
 #[derive(Debug)]
@@ -68,7 +70,7 @@ impl std::fmt::Display for While {
 
 #[derive(Debug)]
 pub struct For {
-    pub ident: String,
+    pub pattern: Pattern,
     pub expr: Arc<dyn Node>,
     pub block: Arc<dyn Node>,
 }
@@ -76,12 +78,11 @@ pub struct For {
 impl Node for For {
     fn eval(&self, ctx: LockableContext) -> ValueResult {
         let mut lastvalue = NoneValue::boxed();
-        let values = self.expr.eval(ctx.clone())?.vec()?;
+        let values = self.expr.eval(ctx.clone())?.iter()?;
         for value in values {
+            let value = value?;
             let ctx = Context::from_parent(ctx.clone());
-            {
-                ctx.write().set(&self.ident, value);
-            }
+            self.pattern.assign_local(&*value, &ctx)?;
             let mut value = self.block.eval(ctx)?;
             if value.get_type_name() == "break" {
                 let value = value.downcast::<BreakValue>().unwrap();
@@ -102,22 +103,24 @@ impl For {
     pub fn parse(
         mut pairs: Pairs<Rule>,
     ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
-        let ident = pairs
-            .next()
-            .expect("due to grammar.pest")
-            .as_span()
-            .as_str()
-            .to_owned();
+        let pattern = Pattern::parse(pairs.next().expect("due to grammar.pest"))?;
         let expr = make_ast(pairs.next().expect("due to grammar.pest"))?;
         let block = make_ast(pairs.next().expect("due to grammar.pest"))?;
-        Ok(Arc::new(Self { ident, expr, block }))
+        Ok(Arc::new(Self {
+            pattern,
+            expr,
+            block,
+        }))
     }
 }
 
 impl std::fmt::Display for For {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut text = String::new();
-        text.push_str(&format!("{} {} {}\n", self.ident, self.expr, self.block));
+        text.push_str(&format!(
+            "{:?} {} {}\n",
+            self.pattern, self.expr, self.block
+        ));
         write!(f, "For '{}'", text)
     }
 }
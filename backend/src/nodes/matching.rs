@@ -0,0 +1,376 @@
+use crate::ast::*;
+use crate::context::Context;
+use crate::context::LockableContext;
+use crate::error::CashError;
+use crate::nodes::StringLiteral;
+use crate::rules::Rule;
+use crate::value::{Value, ValueResult};
+use crate::values::{BooleanValue, DictValue, FloatValue, IntegerValue, ListValue, RangeValue};
+use pest::iterators::{Pair, Pairs};
+use std::sync::Arc;
+
+pub type Bindings = Vec<(String, Box<dyn Value>)>;
+
+#[derive(Debug)]
+pub enum Pattern {
+    /// `_`
+    Wildcard,
+    Binding(String),
+    /// the literal and whether it is negated
+    Literal(Arc<dyn Node>, bool),
+    /// `1..10`, matches numbers inside of the range
+    Range(Arc<dyn Node>),
+    /// `integer(n)`, the type name as returned by `type()`
+    Type(String, Box<Pattern>),
+    /// `[first, ..rest, last]`
+    List {
+        before: Vec<Pattern>,
+        /// `..` and the optional name, which is bound to the remaining items
+        rest: Option<Option<String>>,
+        after: Vec<Pattern>,
+    },
+    /// `{name, age: integer(age)}`, other keys are ignored
+    Dict(Vec<(String, Pattern)>),
+}
+
+impl Pattern {
+    /// Collects the bindings of a successful match
+    pub fn matches(
+        &self,
+        value: &dyn Value,
+        ctx: &LockableContext,
+        bindings: &mut Bindings,
+    ) -> Result<bool, Box<dyn std::error::Error + Sync + Send>> {
+        match self {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Binding(name) => {
+                bindings.push((name.clone(), Value::clone(value)));
+                Ok(true)
+            }
+            Pattern::Literal(node, negative) => {
+                let mut literal = node.eval(ctx.clone())?;
+                if *negative {
+                    literal = literal.uminus()?;
+                }
+                if literal.get_type_name() != value.get_type_name() {
+                    return Ok(false);
+                }
+                if value.get_type_name() == "none" {
+                    return Ok(true);
+                }
+                Ok(value
+                    .eq(&literal)
+                    .ok()
+                    .and_then(|result| result.downcast_ref::<BooleanValue>().map(|b| b.value))
+                    .unwrap_or(false))
+            }
+            Pattern::Range(node) => {
+                let range = node.eval(ctx.clone())?;
+                let number = value.downcast_ref::<IntegerValue>().is_some()
+                    || value.downcast_ref::<FloatValue>().is_some();
+                match range.downcast_ref::<RangeValue>() {
+                    Some(range) if number => {
+                        let contains = range.contains(&Value::clone(value))?;
+                        Ok(matches!(contains.downcast_ref::<BooleanValue>(), Some(b) if b.value))
+                    }
+                    _ => Ok(false),
+                }
+            }
+            Pattern::Type(name, inner) => {
+                if value.get_type_name() == name {
+                    inner.matches(value, ctx, bindings)
+                } else {
+                    Ok(false)
+                }
+            }
+            Pattern::List {
+                before,
+                rest,
+                after,
+            } => {
+                let list = match value.downcast_ref::<ListValue>() {
+                    Some(list) => &list.values,
+                    None => return Ok(false),
+                };
+                let fixed = before.len() + after.len();
+                let length_matches = match rest {
+                    Some(_) => list.len() >= fixed,
+                    None => list.len() == fixed,
+                };
+                if !length_matches {
+                    return Ok(false);
+                }
+                let tail = list.len() - after.len();
+                for (pattern, item) in before.iter().zip(list.iter()) {
+                    if !pattern.matches(&**item, ctx, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                for (pattern, item) in after.iter().zip(list[tail..].iter()) {
+                    if !pattern.matches(&**item, ctx, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                if let Some(Some(name)) = rest {
+                    let remaining = list[before.len()..tail]
+                        .iter()
+                        .map(|item| (**item).clone())
+                        .collect();
+                    bindings.push((name.clone(), ListValue::boxed(remaining)?));
+                }
+                Ok(true)
+            }
+            Pattern::Dict(entries) => {
+                let dict = match value.downcast_ref::<DictValue>() {
+                    Some(dict) => dict,
+                    None => return Ok(false),
+                };
+                for (key, pattern) in entries {
+                    match dict.values.get(key) {
+                        Some(item) => {
+                            if !pattern.matches(&**item, ctx, bindings)? {
+                                return Ok(false);
+                            }
+                        }
+                        None => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Binds the parts of `value` like an assignment, failing if the pattern does not match
+    pub fn assign(
+        &self,
+        value: &dyn Value,
+        ctx: &LockableContext,
+    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        let bindings = self.destructure(value, ctx)?;
+        for (name, _) in &bindings {
+            ctx.read().check_assign(name)?;
+        }
+        let mut lock = ctx.write();
+        for (name, value) in bindings {
+            lock.set(&name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Like `assign`, but always binds in `ctx`, never in one of its parents
+    /// Used for parameters and loop variables, which may not overwrite outer variables
+    pub fn assign_local(
+        &self,
+        value: &dyn Value,
+        ctx: &LockableContext,
+    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        let bindings = self.destructure(value, ctx)?;
+        let mut lock = ctx.write();
+        for (name, value) in bindings {
+            lock.set_local(&name, value)?;
+        }
+        Ok(())
+    }
+
+    /// `let` and `const`, binds in `ctx` like `assign_local`
+    pub fn declare(
+        &self,
+        value: &dyn Value,
+        ctx: &LockableContext,
+        constant: bool,
+    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        let bindings = self.destructure(value, ctx)?;
+        let mut lock = ctx.write();
+        for (name, value) in bindings {
+            if name.starts_with('$') {
+                return CashError::InvalidOperation("declaration".to_owned(), name).boxed();
+            }
+            lock.declare(&name, value, constant)?;
+        }
+        Ok(())
+    }
+
+    /// Matches `value`, also checks the permission to write environment variables
+    fn destructure(
+        &self,
+        value: &dyn Value,
+        ctx: &LockableContext,
+    ) -> Result<Bindings, Box<dyn std::error::Error + Sync + Send>> {
+        let mut bindings = Vec::new();
+        if !self.matches(value, ctx, &mut bindings)? {
+            return CashError::CannotDestructure(value.to_string()).boxed();
+        }
+        for (name, _) in &bindings {
+            if name.starts_with('$') {
+                ctx.read().permissions.check_env_write(name)?;
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn parse(pair: Pair<Rule>) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Pattern::parse_inner(
+            pair.into_inner()
+                .next()
+                .expect("Pattern should contain a node"),
+        )
+    }
+
+    /// Parses the rule inside of a `Pattern`, list and dict patterns may also be used on their own
+    pub fn parse_inner(
+        inner: Pair<Rule>,
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        match inner.as_rule() {
+            Rule::WildcardPattern => Ok(Pattern::Wildcard),
+            Rule::Ident => Ok(Pattern::Binding(inner.as_span().as_str().to_owned())),
+            Rule::Range => Ok(Pattern::Range(make_ast(inner)?)),
+            Rule::LiteralPattern => {
+                let mut parts = inner.into_inner();
+                let first = parts.next().expect("Literal should contain a node");
+                if first.as_rule() == Rule::Negative {
+                    let number = parts
+                        .next()
+                        .expect("Negative should be followed by a number");
+                    Ok(Pattern::Literal(make_ast(number)?, true))
+                } else {
+                    Ok(Pattern::Literal(make_ast(first)?, false))
+                }
+            }
+            Rule::TypePattern => {
+                let mut parts = inner.into_inner();
+                let name = parts.next().unwrap().as_span().as_str().to_owned();
+                let pattern = Pattern::parse(parts.next().unwrap())?;
+                Ok(Pattern::Type(name, Box::new(pattern)))
+            }
+            Rule::ListPattern | Rule::TuplePattern => {
+                let mut before = Vec::new();
+                let mut rest = None;
+                let mut after = Vec::new();
+                for item in inner.into_inner() {
+                    if item.as_rule() == Rule::RestPattern {
+                        if rest.is_some() {
+                            return CashError::InvalidOperation(
+                                "multiple rest patterns".to_owned(),
+                                "list pattern".to_owned(),
+                            )
+                            .boxed();
+                        }
+                        rest = Some(
+                            item.into_inner()
+                                .next()
+                                .map(|name| name.as_span().as_str().to_owned()),
+                        );
+                    } else if rest.is_some() {
+                        after.push(Pattern::parse(item)?);
+                    } else {
+                        before.push(Pattern::parse(item)?);
+                    }
+                }
+                Ok(Pattern::List {
+                    before,
+                    rest,
+                    after,
+                })
+            }
+            Rule::DictPattern => {
+                let mut entries = Vec::new();
+                for entry in inner.into_inner() {
+                    let mut parts = entry.into_inner();
+                    let key = parts.next().unwrap();
+                    let name = if key.as_rule() == Rule::String {
+                        StringLiteral::parse_constant(key.into_inner())?
+                    } else {
+                        key.as_span().as_str().to_owned()
+                    };
+                    let pattern = match parts.next() {
+                        Some(pattern) => Pattern::parse(pattern)?,
+                        None => Pattern::Binding(name.clone()),
+                    };
+                    entries.push((name, pattern));
+                }
+                Ok(Pattern::Dict(entries))
+            }
+            _ => CashError::Bug("Pattern may not contain this".to_owned()).boxed(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Arc<dyn Node>>,
+    pub body: Arc<dyn Node>,
+}
+
+#[derive(Debug)]
+pub struct Match {
+    pub value: Arc<dyn Node>,
+    pub arms: Vec<MatchArm>,
+}
+
+impl Node for Match {
+    fn eval(&self, ctx: LockableContext) -> ValueResult {
+        let value = self.value.eval(ctx.clone())?;
+        for arm in &self.arms {
+            let mut bindings = Vec::new();
+            if !arm.pattern.matches(&*value, &ctx, &mut bindings)? {
+                continue;
+            }
+            let ctx = Context::from_parent(ctx.clone());
+            {
+                let mut lock = ctx.write();
+                for (name, value) in bindings {
+                    lock.set_local(&name, value)?;
+                }
+            }
+            if let Some(guard) = &arm.guard {
+                let val = guard.eval(ctx.clone())?;
+                if let Some(val) = val.downcast_ref::<BooleanValue>() {
+                    if !val.value {
+                        continue;
+                    }
+                } else {
+                    return CashError::InvalidType(
+                        val.get_type_name().to_owned(),
+                        "guard".to_owned(),
+                    )
+                    .boxed();
+                }
+            }
+            return arm.body.eval(ctx);
+        }
+        CashError::NoMatch(value.to_string()).boxed()
+    }
+}
+
+impl Match {
+    pub fn parse_inner(
+        mut pairs: Pairs<Rule>,
+    ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+        let value = make_ast(pairs.next().unwrap())?;
+        let mut arms = Vec::new();
+        for arm in pairs {
+            let mut inner = arm.into_inner();
+            let pattern = Pattern::parse(inner.next().unwrap())?;
+            let mut next = inner.next().unwrap();
+            let mut guard = None;
+            if next.as_rule() == Rule::MatchGuard {
+                guard = Some(make_ast(next.into_inner().next().unwrap())?);
+                next = inner.next().unwrap();
+            }
+            let body = make_ast(next)?;
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+            });
+        }
+        Ok(Arc::new(Self { value, arms }))
+    }
+}
+
+impl std::fmt::Display for Match {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Match '{}' with {} arms", self.value, self.arms.len())
+    }
+}
@@ -1,18 +1,26 @@
 mod assignment;
 mod block;
+mod comprehension;
 mod conditionals;
 mod expr;
 mod ident;
+mod import;
 mod keywordstatement;
 mod literals;
 mod loops;
+mod matching;
 mod pipe;
+mod trycatch;
 pub use assignment::*;
 pub use block::*;
+pub use comprehension::*;
 pub use conditionals::*;
 pub use expr::*;
 pub use ident::*;
+pub use import::*;
 pub use keywordstatement::*;
 pub use literals::*;
 pub use loops::*;
+pub use matching::*;
 pub use pipe::*;
+pub use trycatch::*;
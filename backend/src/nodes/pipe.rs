@@ -2,16 +2,20 @@ use crate::ast::*;
 use crate::context::LockableContext;
 use crate::error::CashError;
 use crate::nodes::StringLiteral;
+use crate::output;
 use crate::rules::Rule;
 use crate::value::ValueResult;
 use crate::values::{NoneValue, StringValue};
 use pest::iterators::Pairs;
+use std::io::Read;
 #[cfg(target_family = "unix")]
-use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::io::{FromRawFd, IntoRawFd};
 #[cfg(target_family = "windows")]
 use std::os::windows::io::{FromRawHandle, IntoRawHandle};
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Stdio};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct EnvCommand {
@@ -28,61 +32,125 @@ pub struct Pipe {
 impl Node for Pipe {
     fn eval(&self, ctx: LockableContext) -> ValueResult {
         // construct pipe
-        let mut cmd = None;
-        for (i, command) in self.commands.iter().enumerate() {
-            let last = i == self.commands.len() - 1;
-            let stdout = if !self.capturing && last {
-                Stdio::inherit()
-            } else {
-                Stdio::piped()
-            };
+        let mut children: Vec<Child> = Vec::with_capacity(self.commands.len());
+        let (permissions, budget, output, cwd) = {
+            let lock = ctx.read();
+            (
+                lock.permissions.clone(),
+                lock.budget.clone(),
+                lock.output.clone(),
+                lock.get_cwd(),
+            )
+        };
+        let (stdout_sink, stderr_sink) = (output.stdout_sink(), output.stderr_sink());
+        let mut forwarders = Vec::new();
+        // evaluate all arguments first, a failing argument should not leave processes running
+        let mut all_args = Vec::with_capacity(self.commands.len());
+        for command in &self.commands {
+            permissions.check_process(&command.name)?;
             let mut args = Vec::with_capacity(command.args.len());
             for arg in &command.args {
                 args.push(format!("{}", arg.eval(ctx.clone())?));
             }
-            if cmd.is_none() {
-                cmd = Some(
-                    Command::new(&command.name)
-                        .args(&args)
-                        .stdout(stdout)
-                        .spawn()
-                        .unwrap(),
-                );
+            all_args.push(args);
+        }
+        for (i, (command, args)) in self.commands.iter().zip(all_args).enumerate() {
+            let last = i == self.commands.len() - 1;
+            let stdout = if !self.capturing && last && stdout_sink.is_none() {
+                Stdio::inherit()
             } else {
-                let prev_stdout = match cmd.expect("cannot happen, is checked").stdout {
+                Stdio::piped()
+            };
+            let mut process = Command::new(&command.name);
+            process.args(&args).stdout(stdout).current_dir(&cwd);
+            if let Some(previous) = children.last_mut() {
+                let prev_stdout = match previous.stdout.take() {
                     Some(stdout) => stdout,
-                    None => return CashError::Bug("No stdout for a command".to_owned()).boxed(),
+                    None => {
+                        stop(&mut children);
+                        return CashError::Bug("No stdout for a command".to_owned()).boxed();
+                    }
                 };
 
                 #[cfg(target_family = "unix")]
-                let stdin = unsafe { Stdio::from_raw_fd(prev_stdout.as_raw_fd()) };
+                let stdin = unsafe { Stdio::from_raw_fd(prev_stdout.into_raw_fd()) };
                 #[cfg(target_family = "windows")]
                 let stdin = unsafe { Stdio::from_raw_handle(prev_stdout.into_raw_handle()) };
 
-                cmd = Some(
-                    Command::new(&command.name)
-                        .args(&args)
-                        .stdout(stdout)
-                        .stdin(stdin)
-                        .spawn()
-                        .unwrap(),
-                );
+                process.stdin(stdin);
+            }
+            if stderr_sink.is_some() {
+                process.stderr(Stdio::piped());
+            }
+            let mut child = match process.spawn() {
+                Ok(child) => child,
+                Err(err) => {
+                    stop(&mut children);
+                    return CashError::CannotSpawn(command.name.clone(), err.to_string()).boxed();
+                }
+            };
+            if let (Some(sink), Some(stderr)) = (&stderr_sink, child.stderr.take()) {
+                let sink = sink.clone();
+                forwarders.push(thread::spawn(move || output::forward(stderr, &sink)));
             }
+            children.push(child);
         }
 
+        // read the output on another thread, so the pipe can be interrupted meanwhile
+        let reader = children
+            .last_mut()
+            .and_then(|child| child.stdout.take())
+            .map(|mut stdout| {
+                let sink = stdout_sink.filter(|_| !self.capturing);
+                thread::spawn(move || {
+                    let mut output = Vec::new();
+                    match sink {
+                        Some(sink) => output::forward(stdout, &sink),
+                        None => stdout.read_to_end(&mut output).map(|_| ()),
+                    }
+                    .map(|_| output)
+                })
+            });
+
         // eval pipe
-        if let Ok(res) = cmd.unwrap().wait_with_output() {
-            if self.capturing {
-                let mut text: String = String::from_utf8_lossy(&res.stdout).to_string();
-                if text.ends_with("\n") {
-                    text.remove(text.len() - 1);
+        loop {
+            if let Err(err) = budget.check_waiting() {
+                stop(&mut children);
+                return Err(err);
+            }
+            match children.last_mut().map(|child| child.try_wait()) {
+                Some(Ok(Some(_))) => break,
+                Some(Ok(None)) => thread::sleep(Duration::from_millis(1)),
+                Some(Err(err)) => {
+                    stop(&mut children);
+                    return Err(err.into());
                 }
-                StringValue::boxed(text)
-            } else {
-                NoneValue::boxed()
+                None => {
+                    return CashError::Bug("A pipe should contain at least one command".to_owned())
+                        .boxed()
+                }
+            }
+        }
+        for child in &mut children {
+            let _ = child.wait();
+        }
+        for forwarder in forwarders {
+            let _ = forwarder.join();
+        }
+
+        let output = match reader.map(|reader| reader.join()) {
+            Some(Ok(Ok(output))) => output,
+            None => Vec::new(),
+            _ => return CashError::Bug("Cannot read the output of a pipe".to_owned()).boxed(),
+        };
+        if self.capturing {
+            let mut text: String = String::from_utf8_lossy(&output).to_string();
+            if text.ends_with("\n") {
+                text.remove(text.len() - 1);
             }
+            StringValue::boxed(text)
         } else {
-            CashError::Bug("".to_owned()).boxed()
+            NoneValue::boxed()
         }
     }
 }
@@ -108,7 +176,16 @@ impl Pipe {
 
             if call.as_rule() == Rule::FunctionCall {
                 for arg in call.into_inner() {
-                    args.push(make_ast(arg)?);
+                    match arg.as_rule() {
+                        Rule::NamedArgument | Rule::Spread | Rule::SpreadNamed => {
+                            return CashError::InvalidOperation(
+                                "named or spread arguments".to_owned(),
+                                "command".to_owned(),
+                            )
+                            .boxed();
+                        }
+                        _ => args.push(make_ast(arg)?),
+                    }
                 }
             } else {
                 for arg in call.into_inner() {
@@ -132,3 +209,11 @@ impl Pipe {
         }))
     }
 }
+
+/// Kills the started processes of a pipe which can not be completed
+fn stop(children: &mut [Child]) {
+    for child in children {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
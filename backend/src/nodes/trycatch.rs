@@ -0,0 +1,90 @@
+use crate::ast::*;
+use crate::context::Context;
+use crate::context::LockableContext;
+use crate::error::CashError;
+use crate::rules::Rule;
+use crate::value::ValueResult;
+use crate::values::ErrorValue;
+use pest::iterators::Pairs;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct Catch {
+    /// the caught error is bound to this name inside of the block
+    pub ident: Option<String>,
+    pub block: Arc<dyn Node>,
+}
+
+/// `try {} catch err {} finally {}`, either `catch` or `finally` may be left out
+#[derive(Debug)]
+pub struct Try {
+    pub block: Arc<dyn Node>,
+    pub catch: Option<Catch>,
+    pub finally: Option<Arc<dyn Node>>,
+}
+
+impl Node for Try {
+    fn eval(&self, ctx: LockableContext) -> ValueResult {
+        let mut result = self.block.eval(Context::from_parent(ctx.clone()));
+        if let (Err(err), Some(catch)) = (&result, &self.catch) {
+            let catchable = CashError::find(&**err).map_or(true, CashError::is_catchable);
+            if catchable {
+                let error = ErrorValue::from_error(&**err);
+                let ctx = Context::from_parent(ctx.clone());
+                if let Some(ident) = &catch.ident {
+                    ctx.write().set_local(ident, Box::new(error))?;
+                }
+                result = catch.block.eval(ctx);
+            }
+        }
+        if let Some(finally) = &self.finally {
+            // an error inside of `finally` replaces the result
+            finally.eval(Context::from_parent(ctx))?;
+        }
+        result
+    }
+}
+
+impl Try {
+    pub fn parse_inner(
+        mut pairs: Pairs<Rule>,
+    ) -> Result<Arc<dyn Node>, Box<dyn std::error::Error + Sync + Send>> {
+        let block = make_ast(pairs.next().expect("due to grammar.pest"))?;
+        let mut catch = None;
+        let mut finally = None;
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::Catch => {
+                    let mut inner = pair.into_inner();
+                    let mut next = inner.next().unwrap();
+                    let mut ident = None;
+                    if next.as_rule() == Rule::Ident {
+                        ident = Some(next.as_span().as_str().to_owned());
+                        next = inner.next().unwrap();
+                    }
+                    catch = Some(Catch {
+                        ident,
+                        block: make_ast(next)?,
+                    });
+                }
+                Rule::Finally => {
+                    finally = Some(make_ast(pair.into_inner().next().unwrap())?);
+                }
+                _ => {
+                    return CashError::Bug("Try may not contain this".to_owned()).boxed();
+                }
+            }
+        }
+        Ok(Arc::new(Self {
+            block,
+            catch,
+            finally,
+        }))
+    }
+}
+
+impl std::fmt::Display for Try {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Try '{}'", self.block)
+    }
+}
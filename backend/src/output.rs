@@ -0,0 +1,72 @@
+use parking_lot::{const_mutex, Mutex};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+pub type Sink = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Where scripts write to, `None` means the stream of the process
+#[derive(Clone, Default)]
+pub struct Output {
+    stdout: Option<Sink>,
+    stderr: Option<Sink>,
+}
+
+impl Output {
+    pub fn set_stdout(&mut self, writer: Box<dyn Write + Send>) {
+        self.stdout = Some(Arc::new(const_mutex(writer)));
+    }
+
+    pub fn set_stderr(&mut self, writer: Box<dyn Write + Send>) {
+        self.stderr = Some(Arc::new(const_mutex(writer)));
+    }
+
+    pub fn write_out(&self, text: &str) -> std::io::Result<()> {
+        match &self.stdout {
+            Some(sink) => write_sink(sink, text.as_bytes()),
+            None => std::io::stdout().lock().write_all(text.as_bytes()),
+        }
+    }
+
+    pub fn write_err(&self, text: &str) -> std::io::Result<()> {
+        match &self.stderr {
+            Some(sink) => write_sink(sink, text.as_bytes()),
+            None => std::io::stderr().lock().write_all(text.as_bytes()),
+        }
+    }
+
+    /// The sink for stdout, if it is not the stdout of the process
+    pub fn stdout_sink(&self) -> Option<Sink> {
+        self.stdout.clone()
+    }
+
+    /// The sink for stderr, if it is not the stderr of the process
+    pub fn stderr_sink(&self) -> Option<Sink> {
+        self.stderr.clone()
+    }
+}
+
+impl std::fmt::Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Output")
+            .field("stdout", &self.stdout.as_ref().map(|_| "sink"))
+            .field("stderr", &self.stderr.as_ref().map(|_| "sink"))
+            .finish()
+    }
+}
+
+fn write_sink(sink: &Sink, bytes: &[u8]) -> std::io::Result<()> {
+    let mut writer = sink.lock();
+    writer.write_all(bytes)?;
+    writer.flush()
+}
+
+/// Copies everything from `reader` to `sink`, e.g. the output of a process
+pub fn forward<R: Read>(mut reader: R, sink: &Sink) -> std::io::Result<()> {
+    let mut buffer = [0; 4096];
+    loop {
+        match reader.read(&mut buffer)? {
+            0 => return Ok(()),
+            read => write_sink(sink, &buffer[..read])?,
+        }
+    }
+}
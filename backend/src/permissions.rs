@@ -0,0 +1,98 @@
+use crate::error::CashError;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Capabilities a script is allowed to use
+/// By default everything is allowed
+#[derive(Debug, Clone)]
+pub struct Permissions {
+    /// spawn executables via pipes
+    pub processes: bool,
+    /// read `$VAR`
+    pub env_read: bool,
+    /// write `$VAR = ...`
+    pub env_write: bool,
+    /// access the filesystem, e.g. via `cd`
+    pub filesystem: bool,
+    /// if set, filesystem access is limited to these directories
+    pub allowed_directories: Option<Vec<PathBuf>>,
+    /// load other files via `import`
+    pub imports: bool,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Permissions {
+            processes: true,
+            env_read: true,
+            env_write: true,
+            filesystem: true,
+            allowed_directories: None,
+            imports: true,
+        }
+    }
+}
+
+impl Permissions {
+    /// Denies everything
+    pub fn none() -> Self {
+        Permissions {
+            processes: false,
+            env_read: false,
+            env_write: false,
+            filesystem: false,
+            allowed_directories: None,
+            imports: false,
+        }
+    }
+
+    pub fn check_process(&self, name: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.processes {
+            Ok(())
+        } else {
+            CashError::PermissionDenied(format!("spawning process '{}'", name)).boxed()
+        }
+    }
+
+    pub fn check_env_read(&self, name: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.env_read {
+            Ok(())
+        } else {
+            CashError::PermissionDenied(format!("reading environment variable '{}'", name)).boxed()
+        }
+    }
+
+    pub fn check_env_write(&self, name: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.env_write {
+            Ok(())
+        } else {
+            CashError::PermissionDenied(format!("writing environment variable '{}'", name)).boxed()
+        }
+    }
+
+    pub fn check_import(&self, path: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.imports {
+            Ok(())
+        } else {
+            CashError::PermissionDenied(format!("importing '{}'", path)).boxed()
+        }
+    }
+
+    /// Paths are canonicalized first, so `..` cannot escape the allowed directories
+    pub fn check_path(&self, path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let denied = || CashError::PermissionDenied(format!("accessing '{}'", path.display()));
+        if !self.filesystem {
+            return denied().boxed();
+        }
+        if let Some(allowed) = &self.allowed_directories {
+            let path = match std::fs::canonicalize(path) {
+                Ok(path) => path,
+                Err(_) => return denied().boxed(),
+            };
+            if !allowed.iter().any(|directory| path.starts_with(directory)) {
+                return denied().boxed();
+            }
+        }
+        Ok(())
+    }
+}
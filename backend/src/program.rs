@@ -0,0 +1,67 @@
+use crate::ast::{make_program, Node};
+use crate::context::LockableContext;
+use crate::diagnostic::{Diagnostic, Diagnostics, SourceMap};
+use crate::rules::{Language, Rule};
+use crate::value::ValueResult;
+use pest::Parser;
+use std::sync::Arc;
+
+/// A compiled script, which can be evaluated many times
+/// Cloning is cheap and programs may be shared between runtimes and threads
+#[derive(Debug, Clone)]
+pub struct Program {
+    root: Arc<dyn Node>,
+}
+
+impl Program {
+    /// Parses `text`, diagnostics refer to it as `file`
+    pub(crate) fn compile(text: &str, file: &str) -> Result<Self, Diagnostics> {
+        let source_map = Arc::new(SourceMap::new(file, text));
+        let text = source_map.text();
+
+        let parse_result = Language::parse(Rule::Main, text);
+        let parse_tree = match parse_result {
+            Ok(parse_tree) => parse_tree,
+            Err(err) => return Err(Diagnostic::from_pest(err, &source_map).into()),
+        };
+        let mut root_nodes = parse_tree.collect::<Vec<_>>();
+        if root_nodes.len() != 1 {
+            return Err(Diagnostic::new(
+                "Error occured while parsing input: Not exactly one root node".to_owned(),
+            )
+            .into());
+        }
+        let root_node = root_nodes.remove(0);
+        let root_span = root_node.as_span();
+        if root_span.end() != text.len() {
+            return Err(Diagnostic::at(
+                "Not all of the input was consumed".to_owned(),
+                source_map.locate(root_span.end(), text.len()),
+            )
+            .into());
+        }
+        let tree_result = make_program(
+            root_node
+                .into_inner()
+                .next()
+                .expect("Main should have a Block"),
+            source_map.clone(),
+        );
+        match tree_result {
+            Ok(root) => Ok(Program { root }),
+            Err(err) => {
+                Err(Diagnostic::new(format!("Error occured while parsing input: {}", err)).into())
+            }
+        }
+    }
+
+    pub(crate) fn eval(&self, ctx: LockableContext) -> ValueResult {
+        self.root.eval(ctx)
+    }
+}
+
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Program '{}'", self.root)
+    }
+}
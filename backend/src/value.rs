@@ -1,9 +1,13 @@
 use crate::context::LockableContext;
 use crate::error::CashError;
+use crate::values::IteratorValue;
 use downcast_rs::{impl_downcast, DowncastSync};
+use std::collections::HashMap;
 use std::error::Error;
 
 pub type ValueResult = Result<Box<dyn Value>, Box<dyn Error + Send + Sync>>;
+/// Arguments passed by name, e.g. `connect(host = "x")`
+pub type NamedArguments = HashMap<String, Box<dyn Value>>;
 
 pub trait Value: DowncastSync + std::fmt::Display + std::fmt::Debug {
     fn get_type_name(&self) -> &'static str;
@@ -21,6 +25,17 @@ pub trait Value: DowncastSync + std::fmt::Display + std::fmt::Debug {
         CashError::InvalidOperation("function call".to_owned(), self.get_type_name().to_owned())
             .boxed()
     }
+    fn call_named(
+        &self,
+        params: Vec<Box<dyn Value>>,
+        named: NamedArguments,
+        ctx: LockableContext,
+    ) -> ValueResult {
+        match named.keys().next() {
+            Some(name) => CashError::UnknownArgument(name.clone()).boxed(),
+            None => self.call(params, ctx),
+        }
+    }
     fn not(self: Box<Self>) -> ValueResult {
         CashError::InvalidOperation("not".to_owned(), self.get_type_name().to_owned()).boxed()
     }
@@ -107,6 +122,11 @@ pub trait Value: DowncastSync + std::fmt::Display + std::fmt::Debug {
         CashError::InvalidOperation("or".to_owned(), self.get_type_name().to_owned()).boxed()
     }
     fn clone(&self) -> Box<dyn Value>;
+    /// A stateful iterator over the items, by default over the items of `vec`
+    fn iter(self: Box<Self>) -> Result<IteratorValue, Box<dyn Error + Send + Sync>> {
+        let values = self.vec()?;
+        Ok(IteratorValue::new(values.into_iter().map(Ok)))
+    }
     fn vec(
         self: Box<Self>,
     ) -> Result<Vec<Box<dyn Value>>, Box<dyn std::error::Error + Send + Sync>> {
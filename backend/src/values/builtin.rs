@@ -1,49 +1,85 @@
 use crate::context::LockableContext;
-use crate::value::{Value, ValueResult};
+use crate::error::CashError;
+use crate::value::{NamedArguments, Value, ValueResult};
+use std::collections::HashMap;
+use std::sync::Arc;
 
-pub struct BuiltInFunction<
-    F: 'static + Fn(Vec<Box<dyn Value>>, LockableContext) -> ValueResult + Send + Sync + Sync,
-> {
-    pub closure: &'static F,
+pub type BuiltInClosure = dyn Fn(Vec<Box<dyn Value>>, NamedArguments, LockableContext) -> ValueResult
+    + Send
+    + Sync
+    + 'static;
+
+pub struct BuiltInFunction {
+    pub closure: Arc<BuiltInClosure>,
 }
 
-impl<F: 'static + Fn(Vec<Box<dyn Value>>, LockableContext) -> ValueResult + Send + Sync + Sync>
-    Value for BuiltInFunction<F>
-{
+impl Value for BuiltInFunction {
     fn get_type_name(&self) -> &'static str {
         "builtin_function"
     }
 
     fn call(&self, params: Vec<Box<dyn Value>>, ctx: LockableContext) -> ValueResult {
-        (self.closure)(params, ctx)
+        (self.closure)(params, HashMap::new(), ctx)
+    }
+    fn call_named(
+        &self,
+        params: Vec<Box<dyn Value>>,
+        named: NamedArguments,
+        ctx: LockableContext,
+    ) -> ValueResult {
+        (self.closure)(params, named, ctx)
     }
     fn clone(&self) -> Box<dyn Value> {
         Box::new(Self {
-            closure: self.closure,
+            closure: self.closure.clone(),
         })
     }
 }
 
-impl<F: 'static + Fn(Vec<Box<dyn Value>>, LockableContext) -> ValueResult + Send + Sync + Sync>
-    BuiltInFunction<F>
-{
+impl BuiltInFunction {
     // different boxed than in other value types
-    pub fn boxed(closure: &'static F) -> Option<Box<dyn Value>> {
-        Some(Box::new(BuiltInFunction { closure }))
+    pub fn boxed<F>(closure: &'static F) -> Option<Box<dyn Value>>
+    where
+        F: 'static + Fn(Vec<Box<dyn Value>>, LockableContext) -> ValueResult + Send + Sync,
+    {
+        Some(BuiltInFunction::from_closure(closure))
+    }
+
+    /// Wraps an owned closure, which may capture state of the host application
+    /// Calls with named arguments fail
+    pub fn from_closure<F>(closure: F) -> Box<dyn Value>
+    where
+        F: 'static + Fn(Vec<Box<dyn Value>>, LockableContext) -> ValueResult + Send + Sync,
+    {
+        BuiltInFunction::with_named(move |params, named, ctx| {
+            if let Some(name) = named.keys().next() {
+                return CashError::UnknownArgument(name.clone()).boxed();
+            }
+            closure(params, ctx)
+        })
+    }
+
+    /// Wraps a closure, which also receives the named arguments, e.g. `connect(port = 22)`
+    pub fn with_named<F>(closure: F) -> Box<dyn Value>
+    where
+        F: 'static
+            + Fn(Vec<Box<dyn Value>>, NamedArguments, LockableContext) -> ValueResult
+            + Send
+            + Sync,
+    {
+        Box::new(BuiltInFunction {
+            closure: Arc::new(closure),
+        })
     }
 }
 
-impl<F: 'static + Fn(Vec<Box<dyn Value>>, LockableContext) -> ValueResult + Send + Sync + Sync>
-    std::fmt::Debug for BuiltInFunction<F>
-{
+impl std::fmt::Debug for BuiltInFunction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.get_type_name())
     }
 }
 
-impl<F: 'static + Fn(Vec<Box<dyn Value>>, LockableContext) -> ValueResult + Send + Sync + Sync>
-    std::fmt::Display for BuiltInFunction<F>
-{
+impl std::fmt::Display for BuiltInFunction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.get_type_name())
     }
@@ -0,0 +1,89 @@
+use crate::diagnostic::Location;
+use crate::error::{CashError, TracedError};
+use crate::value::{Value, ValueResult};
+use crate::values::{NoneValue, StringValue};
+
+/// An error caught by `catch` or created by `error()`
+#[derive(Debug)]
+pub struct ErrorValue {
+    /// e.g. `key_not_found`, `error` for errors created by scripts
+    pub kind: String,
+    pub message: String,
+    pub data: Box<dyn Value>,
+    pub location: Option<Location>,
+}
+
+impl ErrorValue {
+    pub fn boxed(message: String, data: Box<dyn Value>) -> ValueResult {
+        Ok(Box::new(ErrorValue {
+            kind: "error".to_owned(),
+            message,
+            data,
+            location: None,
+        }))
+    }
+
+    /// Converts an error returned by a node, keeping the location of a `TracedError`
+    pub fn from_error(err: &(dyn std::error::Error + 'static)) -> Self {
+        let traced = err.downcast_ref::<TracedError>();
+        let location = traced.and_then(|traced| traced.location.clone());
+        match CashError::find(err) {
+            Some(CashError::Thrown(value)) => ErrorValue {
+                kind: value.kind.clone(),
+                message: value.message.clone(),
+                data: value.data.clone(),
+                location: value.location.clone().or(location),
+            },
+            Some(error) => ErrorValue {
+                kind: error.kind().to_owned(),
+                message: error.to_string(),
+                data: Box::new(NoneValue),
+                location,
+            },
+            None => ErrorValue {
+                kind: "external".to_owned(),
+                message: match traced {
+                    Some(traced) => traced.inner().to_string(),
+                    None => err.to_string(),
+                },
+                data: Box::new(NoneValue),
+                location,
+            },
+        }
+    }
+}
+
+impl Value for ErrorValue {
+    fn get_type_name(&self) -> &'static str {
+        "error"
+    }
+
+    /// `kind`, `message`, `data` and `location`, which is `none` if it is not known
+    fn index(&self, index: &Box<dyn Value>) -> ValueResult {
+        match index.to_string().as_str() {
+            "kind" => StringValue::boxed(self.kind.clone()),
+            "message" => StringValue::boxed(self.message.clone()),
+            "data" => Ok(self.data.clone()),
+            "location" => match &self.location {
+                Some(location) => StringValue::boxed(location.to_string()),
+                None => NoneValue::boxed(),
+            },
+            key => CashError::KeyNotFound(key.to_owned(), self.get_type_name().to_owned()).boxed(),
+        }
+    }
+
+    fn clone(&self) -> Box<dyn Value> {
+        Box::new(ErrorValue {
+            kind: self.kind.clone(),
+            message: self.message.clone(),
+            data: self.data.clone(),
+            location: self.location.clone(),
+        })
+    }
+}
+
+impl std::fmt::Display for ErrorValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
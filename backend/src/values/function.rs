@@ -1,18 +1,27 @@
 use crate::ast::Node;
 use crate::context::Context;
-use crate::value::{Value, ValueResult};
-use crate::values::ReturnValue;
+use crate::nodes::Pattern;
+use crate::value::{NamedArguments, Value, ValueResult};
+use crate::values::{Generator, IteratorValue, ListValue, ReturnValue};
 
-use crate::error::CashError;
+use crate::error::{CashError, TracedError};
 
 use crate::context::LockableContext;
+use std::collections::HashMap;
 use std::sync::Arc;
 
+/// The pattern the argument is bound to and its default value
+pub type Parameter = (Arc<Pattern>, Option<Box<dyn Value>>);
+
 #[derive(Debug)]
 pub struct FunctionValue {
     pub node: Arc<dyn Node>,
     pub ctx: LockableContext,
-    pub params: Vec<(String, Option<Box<dyn Value>>)>,
+    pub params: Vec<Parameter>,
+    /// `...rest`, receives the remaining positional arguments as a list
+    pub rest: Option<String>,
+    /// the body contains `yield`, calls return an iterator instead of evaluating it
+    pub generator: bool,
 }
 
 impl Value for FunctionValue {
@@ -20,39 +29,80 @@ impl Value for FunctionValue {
         "function"
     }
 
-    fn call(&self, params: Vec<Box<dyn Value>>, _ctx: LockableContext) -> ValueResult {
+    fn call(&self, params: Vec<Box<dyn Value>>, caller: LockableContext) -> ValueResult {
+        self.call_named(params, HashMap::new(), caller)
+    }
+
+    /// Missing arguments without a default value return a function, which takes the missing ones
+    fn call_named(
+        &self,
+        params: Vec<Box<dyn Value>>,
+        mut named: NamedArguments,
+        caller: LockableContext,
+    ) -> ValueResult {
+        // variables come from the closure, the state of the running task from the caller
+        let (depth, cwd) = {
+            let lock = caller.read();
+            (lock.depth + 1, lock.cwd.clone())
+        };
         let ctx = Context::from_parent(self.ctx.clone());
+        {
+            let mut ctx_lock = ctx.write();
+            ctx_lock.budget.check_depth(depth)?;
+            ctx_lock.depth = depth;
+            ctx_lock.cwd = cwd;
+        }
         let param_count = params.len();
-        if param_count > self.params.len() {
+        if param_count > self.params.len() && self.rest.is_none() {
             return CashError::InvalidParameterCount(param_count, self.params.len()).boxed();
         }
         let mut user_values = params.into_iter();
-        {
-            let mut ctx_lock = ctx.write();
-            let mut iter = self.params.iter();
-            while let Some((name, val)) = iter.next() {
-                if let Some(user_value) = user_values.next() {
-                    ctx_lock.set(name, user_value);
-                } else if let Some(default_value) = val {
-                    ctx_lock.set(name, (*default_value).clone());
-                } else {
-                    let mut new_params = Vec::new();
-                    new_params.push((name.to_string(), None));
-                    while let Some((name, val)) = iter.next() {
-                        if let Some(val) = val {
-                            new_params.push((name.to_string(), Some((*val).clone())));
-                        } else {
-                            new_params.push((name.to_string(), None));
-                        }
-                    }
-                    drop(ctx_lock);
-                    return FunctionValue::boxed(self.node.clone(), ctx, new_params);
-                    //return CashError::InvalidParameterCount(param_count, self.params.len())
-                    // .boxed();
+        let mut missing = Vec::new();
+        for (pattern, val) in &self.params {
+            let name = match &**pattern {
+                Pattern::Binding(name) => Some(name),
+                _ => None,
+            };
+            let named_value = name.and_then(|name| named.remove(name));
+            let value = if let Some(user_value) = user_values.next() {
+                if named_value.is_some() {
+                    return CashError::DuplicateArgument(name.unwrap().clone()).boxed();
                 }
-            }
+                user_value
+            } else if let Some(named_value) = named_value {
+                named_value
+            } else if !missing.is_empty() || val.is_none() {
+                // later parameters keep their default value for the curried function
+                missing.push((pattern.clone(), val.as_ref().map(|val| (**val).clone())));
+                continue;
+            } else {
+                (**val.as_ref().unwrap()).clone()
+            };
+            pattern.assign_local(&*value, &ctx)?;
+        }
+        if let Some(name) = named.keys().next() {
+            return CashError::UnknownArgument(name.clone()).boxed();
+        }
+        if !missing.is_empty() {
+            return Ok(Box::new(FunctionValue {
+                node: self.node.clone(),
+                ctx,
+                params: missing,
+                rest: self.rest.clone(),
+                generator: self.generator,
+            }));
         }
-        let value = self.node.eval(ctx)?;
+        if let Some(rest) = &self.rest {
+            let remaining = ListValue::boxed(user_values.collect())?;
+            ctx.write().set_local(rest, remaining)?;
+        }
+        if self.generator {
+            return IteratorValue::boxed(Generator::new(self.node.clone(), ctx));
+        }
+        let value = self
+            .node
+            .eval(ctx)
+            .map_err(|err| TracedError::push_frame(err, self.node.span()))?;
         if value.get_type_name() == "return" {
             let value = value.downcast::<ReturnValue>().unwrap();
             Ok(value.value)
@@ -62,17 +112,19 @@ impl Value for FunctionValue {
     }
     fn clone(&self) -> Box<dyn Value> {
         let mut params = Vec::with_capacity(self.params.len());
-        for (name, value) in &self.params {
+        for (pattern, value) in &self.params {
             let mut optional = None;
             if let Some(value) = value {
                 optional = Some((*value).clone());
             }
-            params.push((name.clone(), optional));
+            params.push((pattern.clone(), optional));
         }
         Box::new(Self {
             node: self.node.clone(),
             ctx: self.ctx.clone(),
             params,
+            rest: self.rest.clone(),
+            generator: self.generator,
         })
     }
 }
@@ -82,9 +134,17 @@ impl FunctionValue {
     pub fn boxed(
         node: Arc<dyn Node>,
         ctx: LockableContext,
-        params: Vec<(String, Option<Box<dyn Value>>)>,
+        params: Vec<Parameter>,
+        rest: Option<String>,
+        generator: bool,
     ) -> ValueResult {
-        Ok(Box::new(FunctionValue { node, ctx, params }))
+        Ok(Box::new(FunctionValue {
+            node,
+            ctx,
+            params,
+            rest,
+            generator,
+        }))
     }
 }
 
@@ -1,4 +1,5 @@
 use crate::ast::Node;
+use crate::budget::Budget;
 use crate::context::LockableContext;
 use crate::executor::Executor;
 use crate::value::{Value, ValueResult};
@@ -9,13 +10,22 @@ use std::sync::Arc;
 pub struct FutureValue {
     pub id: usize,
     pub executor: Arc<Mutex<Executor>>,
+    /// budget of the runtime that started the job, limits the time spent awaiting it
+    pub budget: Arc<Budget>,
 }
 
 impl FutureValue {
     pub fn boxed(node: Arc<dyn Node>, ctx: LockableContext) -> ValueResult {
-        let executor = ctx.read().get_executor();
+        let (executor, budget) = {
+            let lock = ctx.read();
+            (lock.get_executor(), lock.budget.clone())
+        };
         let id = executor.lock().register_job(node, ctx);
-        Ok(Box::new(FutureValue { id, executor }))
+        Ok(Box::new(FutureValue {
+            id,
+            executor,
+            budget,
+        }))
     }
 }
 
@@ -27,7 +37,8 @@ impl Value for FutureValue {
         "future"
     }
     fn r#await(self: Box<Self>) -> ValueResult {
-        self.executor.lock().get_result(self.id)
+        let waiter = self.executor.lock().waiter(self.id)?;
+        waiter.wait(&self.budget)
     }
 }
 
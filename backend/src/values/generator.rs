@@ -0,0 +1,141 @@
+use crate::ast::Node;
+use crate::budget::{Budget, STACK_SIZE};
+use crate::context::LockableContext;
+use crate::error::{CashError, TracedError};
+use crate::value::{Value, ValueResult};
+use parking_lot::{const_mutex, Mutex};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// how often a suspended body checks whether the runtime was interrupted, timed out or dropped
+const RESUME_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Hands the values of `yield` to the consumer of a generator and waits until the next one is requested
+#[derive(Debug)]
+pub struct Yielder {
+    /// `None` once the body has finished
+    values: SyncSender<Option<ValueResult>>,
+    resume: Mutex<Receiver<()>>,
+    budget: Arc<Budget>,
+}
+
+impl Yielder {
+    /// Fails with `GeneratorClosed` if the consumer dropped the generator
+    /// The limits of the runtime apply while waiting, so a suspended body does not outlive them
+    pub fn yield_value(
+        &self,
+        value: Box<dyn Value>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.values.send(Some(Ok(value))).is_err() {
+            return CashError::GeneratorClosed.boxed();
+        }
+        let resume = self.resume.lock();
+        loop {
+            match resume.recv_timeout(RESUME_CHECK_INTERVAL) {
+                Ok(()) => return Ok(()),
+                Err(RecvTimeoutError::Timeout) => self.budget.check_waiting()?,
+                Err(RecvTimeoutError::Disconnected) => return CashError::GeneratorClosed.boxed(),
+            }
+        }
+    }
+}
+
+/// The body of a generator function, which runs on its own thread until the next `yield`
+/// The thread is started by the first call to `next`
+pub struct Generator {
+    node: Arc<dyn Node>,
+    ctx: LockableContext,
+    running: Option<(Receiver<Option<ValueResult>>, SyncSender<()>)>,
+    done: bool,
+}
+
+impl Generator {
+    pub fn new(node: Arc<dyn Node>, ctx: LockableContext) -> Self {
+        Generator {
+            node,
+            ctx,
+            running: None,
+            done: false,
+        }
+    }
+
+    fn start(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // a buffer of one value, so that the body never blocks when it sends its last result
+        let (values, receiver) = sync_channel(1);
+        let (resume, resume_receiver) = sync_channel(1);
+        let budget = self.ctx.read().budget.clone();
+        self.ctx.write().generator = Some(Arc::new(Yielder {
+            values: values.clone(),
+            resume: const_mutex(resume_receiver),
+            budget,
+        }));
+        let node = self.node.clone();
+        let ctx = self.ctx.clone();
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let result = node
+                    .eval(ctx)
+                    .map_err(|err| TracedError::push_frame(err, node.span()));
+                let _ = match result {
+                    Ok(_) => values.send(None),
+                    Err(err) => values.send(Some(Err(err))),
+                };
+            })?;
+        self.running = Some((receiver, resume));
+        Ok(())
+    }
+}
+
+impl Iterator for Generator {
+    type Item = ValueResult;
+
+    fn next(&mut self) -> Option<ValueResult> {
+        if self.done {
+            return None;
+        }
+        match &self.running {
+            None => {
+                if let Err(err) = self.start() {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+            Some((_, resume)) => {
+                if resume.send(()).is_err() {
+                    self.done = true;
+                    return None;
+                }
+            }
+        }
+        let budget = self.ctx.read().budget.clone();
+        let (values, _) = self.running.as_ref().expect("was started before");
+        // the limits of the runtime apply while the body is running
+        let received = loop {
+            match values.recv_timeout(Duration::from_millis(1)) {
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Err(err) = budget.check_waiting() {
+                        // closes the channels, so the thread of the body stops
+                        self.running = None;
+                        self.done = true;
+                        return Some(Err(err));
+                    }
+                }
+                received => break received,
+            }
+        };
+        match received {
+            Ok(Some(Ok(value))) => Some(Ok(value)),
+            Ok(Some(Err(err))) => {
+                self.done = true;
+                Some(Err(err))
+            }
+            Ok(None) | Err(_) => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
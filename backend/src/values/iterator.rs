@@ -0,0 +1,71 @@
+use crate::value::{Value, ValueResult};
+use parking_lot::{const_mutex, Mutex};
+use std::sync::Arc;
+
+pub type ValueIterator = dyn Iterator<Item = ValueResult> + Send;
+
+/// A lazy sequence of values, e.g. a range or a generator
+/// Clones share the position, every item is only produced once
+#[derive(Clone)]
+pub struct IteratorValue {
+    iterator: Arc<Mutex<Box<ValueIterator>>>,
+}
+
+impl IteratorValue {
+    pub fn new<I>(iterator: I) -> Self
+    where
+        I: Iterator<Item = ValueResult> + Send + 'static,
+    {
+        IteratorValue {
+            iterator: Arc::new(const_mutex(Box::new(iterator))),
+        }
+    }
+
+    pub fn boxed<I>(iterator: I) -> ValueResult
+    where
+        I: Iterator<Item = ValueResult> + Send + 'static,
+    {
+        Ok(Box::new(IteratorValue::new(iterator)))
+    }
+}
+
+impl Iterator for IteratorValue {
+    type Item = ValueResult;
+
+    fn next(&mut self) -> Option<ValueResult> {
+        self.iterator.lock().next()
+    }
+}
+
+impl Value for IteratorValue {
+    fn get_type_name(&self) -> &'static str {
+        "iterator"
+    }
+
+    fn iter(self: Box<Self>) -> Result<IteratorValue, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(*self)
+    }
+
+    /// Consumes the remaining items
+    fn vec(
+        self: Box<Self>,
+    ) -> Result<Vec<Box<dyn Value>>, Box<dyn std::error::Error + Sync + Send>> {
+        self.collect()
+    }
+
+    fn clone(&self) -> Box<dyn Value> {
+        Box::new(std::clone::Clone::clone(self))
+    }
+}
+
+impl std::fmt::Debug for IteratorValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.get_type_name())
+    }
+}
+
+impl std::fmt::Display for IteratorValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.get_type_name())
+    }
+}
@@ -27,19 +27,7 @@ impl ListValue {
                 BooleanValue::boxed(true)
             }
         } else if let Some(other) = index.downcast_ref::<RangeValue>() {
-            if other.lower < 0 || other.upper > self.values.len() as i64 {
-                BooleanValue::boxed(false)
-            } else {
-                let mut v = Vec::with_capacity((other.upper - other.lower) as usize);
-                let _ = self
-                    .values
-                    .iter()
-                    .skip(other.lower as usize)
-                    .take((other.upper - other.lower) as usize)
-                    .map(|val| v.push((*val).clone()))
-                    .collect::<Vec<_>>();
-                BooleanValue::boxed(true)
-            }
+            BooleanValue::boxed(other.fits(self.values.len() as i64))
         } else {
             CashError::InvalidOperation("index".to_owned(), "list ".to_owned() + typename).boxed()
         }
@@ -107,20 +95,14 @@ impl Value for ListValue {
                 self.values[index].indexed_set(value, &indexes[1..])
             }
         } else if let Some(other) = indexes[0].downcast_ref::<RangeValue>() {
-            if other.lower < 0 {
-                CashError::IndexOutOfBounds(other.lower, self.get_type_name().to_owned()).boxed()
-            } else if other.upper > self.values.len() as i64 {
-                CashError::IndexOutOfBounds(other.upper, self.get_type_name().to_owned()).boxed()
-            } else {
-                for i in other.lower..other.upper {
-                    if indexes.len() == 1 {
-                        self.values[i as usize] = (*value).clone();
-                    } else {
-                        self.values[i as usize].indexed_set((*value).clone(), &indexes[1..])?;
-                    }
+            for i in other.indices(Some(self.values.len() as i64)) {
+                if indexes.len() == 1 {
+                    self.values[i as usize] = (*value).clone();
+                } else {
+                    self.values[i as usize].indexed_set((*value).clone(), &indexes[1..])?;
                 }
-                Ok(())
             }
+            Ok(())
         } else {
             CashError::InvalidOperation("indexing ".to_owned(), "list ".to_owned() + "todo").boxed()
         }
@@ -141,21 +123,11 @@ impl Value for ListValue {
                 Ok(self.values[index].clone())
             }
         } else if let Some(other) = index.downcast_ref::<RangeValue>() {
-            if other.lower < 0 {
-                CashError::IndexOutOfBounds(other.lower, self.get_type_name().to_owned()).boxed()
-            } else if other.upper > self.values.len() as i64 {
-                CashError::IndexOutOfBounds(other.upper, self.get_type_name().to_owned()).boxed()
-            } else {
-                let mut v = Vec::with_capacity((other.upper - other.lower) as usize);
-                let _ = self
-                    .values
-                    .iter()
-                    .skip(other.lower as usize)
-                    .take((other.upper - other.lower) as usize)
-                    .map(|val| v.push((*val).clone()))
-                    .collect::<Vec<_>>();
-                ListValue::boxed(v)
-            }
+            let v = other
+                .indices(Some(self.values.len() as i64))
+                .map(|i| self.values[i as usize].clone())
+                .collect();
+            ListValue::boxed(v)
         } else {
             CashError::InvalidOperation("index".to_owned(), "string ".to_owned() + typename).boxed()
         }
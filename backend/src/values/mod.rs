@@ -1,10 +1,13 @@
 mod boolean;
 mod builtin;
 mod dict;
+mod error;
 mod float;
 mod function;
 mod future;
+mod generator;
 mod integer;
+mod iterator;
 mod list;
 mod none;
 mod range;
@@ -13,10 +16,13 @@ mod wrapped;
 pub use boolean::*;
 pub use builtin::*;
 pub use dict::*;
+pub use error::*;
 pub use float::*;
 pub use function::*;
 pub use future::*;
+pub use generator::*;
 pub use integer::*;
+pub use iterator::*;
 pub use list::*;
 pub use none::*;
 pub use range::*;
@@ -1,24 +1,137 @@
 use crate::error::CashError;
 use crate::value::{Value, ValueResult};
-use crate::values::{BooleanValue, FloatValue, IntegerValue};
+use crate::values::{BooleanValue, FloatValue, IntegerValue, IteratorValue};
 
+/// `lower..upper`, `lower..=upper`, `..upper` or `lower..`, optionally followed by `step n`
+/// Ranges with `lower > upper` are descending
 #[derive(Debug, Clone)]
 pub struct RangeValue {
-    pub lower: i64,
-    pub upper: i64,
+    /// `None` for `..upper`, which starts at 0 when iterated or sliced
+    pub lower: Option<i64>,
+    /// `None` for `lower..`, which never ends when iterated and ends at the last item when sliced
+    pub upper: Option<i64>,
+    pub inclusive: bool,
+    /// distance between two items, always positive
+    pub step: i64,
 }
 
 impl RangeValue {
+    pub fn new(lower: Option<i64>, upper: Option<i64>, inclusive: bool) -> Self {
+        RangeValue {
+            lower,
+            upper,
+            inclusive,
+            step: 1,
+        }
+    }
+
     pub fn boxed(lower: i64, upper: i64) -> ValueResult {
-        let length = upper - lower;
-        if length < 1 {
-            CashError::InvalidLength(length, "range".to_owned()).boxed()
+        Ok(Box::new(RangeValue::new(Some(lower), Some(upper), false)))
+    }
+
+    /// Creates a range from the values of its bounds, which have to be integers
+    pub fn from_bounds(
+        lower: Option<&dyn Value>,
+        upper: Option<&dyn Value>,
+        inclusive: bool,
+    ) -> ValueResult {
+        let bound = |value: Option<&dyn Value>| match value {
+            None => Ok(None),
+            Some(value) => match value.downcast_ref::<IntegerValue>() {
+                Some(value) => Ok(Some(value.value)),
+                None => {
+                    CashError::InvalidType(value.get_type_name().to_owned(), "range".to_owned())
+                        .boxed()
+                }
+            },
+        };
+        Ok(Box::new(RangeValue::new(
+            bound(lower)?,
+            bound(upper)?,
+            inclusive,
+        )))
+    }
+
+    /// `range step n`
+    pub fn with_step(mut self: Box<Self>, step: &dyn Value) -> ValueResult {
+        match step.downcast_ref::<IntegerValue>() {
+            Some(step) if step.value > 0 => {
+                self.step = step.value;
+                Ok(self)
+            }
+            Some(step) => {
+                CashError::InvalidValue(step.value.to_string(), "step".to_owned()).boxed()
+            }
+            None => {
+                CashError::InvalidType(step.get_type_name().to_owned(), "step".to_owned()).boxed()
+            }
+        }
+    }
+
+    /// The integers of the range
+    /// With a `length` the range is a slice: negative bounds count from the end and the indices stay inside of `0..length`
+    pub fn indices(&self, length: Option<i64>) -> Box<dyn Iterator<Item = i64> + Send> {
+        let resolve = |bound: i64| match length {
+            Some(length) if bound < 0 => bound + length,
+            _ => bound,
+        };
+        let lower = self.lower.map(resolve).unwrap_or(0);
+        let step = self.step as usize;
+        let upper = match self.upper.map(resolve).or(length) {
+            Some(upper) => upper,
+            None => return Box::new((lower..).step_by(step)),
+        };
+        let descending = lower > upper;
+        let end = match (self.inclusive && self.upper.is_some(), descending) {
+            (false, _) => upper,
+            (true, false) => upper + 1,
+            (true, true) => upper - 1,
+        };
+        if descending {
+            let (start, end) = match length {
+                Some(length) => (lower.min(length - 1), end.max(-1)),
+                None => (lower, end),
+            };
+            Box::new(((end + 1)..=start).rev().step_by(step))
         } else {
-            Ok(Box::new(RangeValue { lower, upper }))
+            let (start, end) = match length {
+                Some(length) => (lower.max(0), end.min(length)),
+                None => (lower, end),
+            };
+            Box::new((start..end).step_by(step))
         }
     }
-}
 
+    /// Whether the bounds of the range are inside of a sequence with `length` items
+    pub fn fits(&self, length: i64) -> bool {
+        let fits = |bound: i64| -length <= bound && bound <= length;
+        self.lower.map_or(true, fits) && self.upper.map_or(true, fits)
+    }
+
+    fn contains_number(&self, value: f64, integer: bool) -> bool {
+        let lower = self.lower.map(|lower| lower as f64);
+        let upper = self.upper.map(|upper| upper as f64);
+        let descending = matches!((lower, upper), (Some(lower), Some(upper)) if lower > upper);
+        let (after_lower, before_upper) = if descending {
+            (
+                lower.map_or(true, |lower| value <= lower),
+                upper.map_or(true, |upper| {
+                    value > upper || (self.inclusive && value == upper)
+                }),
+            )
+        } else {
+            (
+                lower.map_or(true, |lower| value >= lower),
+                upper.map_or(true, |upper| {
+                    value < upper || (self.inclusive && value == upper)
+                }),
+            )
+        };
+        let on_step =
+            !integer || self.step == 1 || (value as i64 - self.lower.unwrap_or(0)) % self.step == 0;
+        after_lower && before_upper && on_step
+    }
+}
 impl Value for RangeValue {
     fn clone(&self) -> Box<dyn Value> {
         Box::new(std::clone::Clone::clone(self))
@@ -30,21 +143,28 @@ impl Value for RangeValue {
         Ok(self)
     }
     fn uminus(mut self: Box<Self>) -> ValueResult {
-        let temp = -self.upper;
-        self.upper = -self.lower;
+        let temp = self.upper.map(|upper| -upper);
+        self.upper = self.lower.map(|lower| -lower);
         self.lower = temp;
         Ok(self)
     }
     fn multiply(mut self: Box<Self>, value: &Box<dyn Value>) -> ValueResult {
         let typename = value.get_type_name();
-        let length = self.upper - self.lower;
+        let (lower, upper) = match (self.lower, self.upper) {
+            (Some(lower), Some(upper)) => (lower, upper),
+            _ => {
+                return CashError::InvalidOperation("multiply".to_owned(), "open range".to_owned())
+                    .boxed()
+            }
+        };
+        let length = upper - lower;
         if let Some(other) = value.downcast_ref::<IntegerValue>() {
             let newlength = length * other.value;
-            self.upper = self.lower + newlength;
+            self.upper = Some(lower + newlength);
             Ok(self)
         } else if let Some(other) = value.downcast_ref::<FloatValue>() {
             let newlength = (length as f64 * other.value) as i64;
-            self.upper = self.lower + newlength;
+            self.upper = Some(lower + newlength);
             Ok(self)
         } else {
             CashError::InvalidOperation("multiply".to_owned(), "range ".to_owned() + typename)
@@ -53,14 +173,21 @@ impl Value for RangeValue {
     }
     fn division(mut self: Box<Self>, value: &Box<dyn Value>) -> ValueResult {
         let typename = value.get_type_name();
-        let length = self.upper - self.lower;
+        let (lower, upper) = match (self.lower, self.upper) {
+            (Some(lower), Some(upper)) => (lower, upper),
+            _ => {
+                return CashError::InvalidOperation("division".to_owned(), "open range".to_owned())
+                    .boxed()
+            }
+        };
+        let length = upper - lower;
         if let Some(other) = value.downcast_ref::<IntegerValue>() {
             let newlength = length / other.value;
-            self.upper = self.lower + newlength;
+            self.upper = Some(lower + newlength);
             Ok(self)
         } else if let Some(other) = value.downcast_ref::<FloatValue>() {
             let newlength = (length as f64 * other.value) as i64;
-            self.upper = self.lower + newlength;
+            self.upper = Some(lower + newlength);
             Ok(self)
         } else {
             CashError::InvalidOperation("division".to_owned(), "range ".to_owned() + typename)
@@ -70,8 +197,8 @@ impl Value for RangeValue {
     fn add(mut self: Box<Self>, value: &Box<dyn Value>) -> ValueResult {
         let typename = value.get_type_name();
         if let Some(other) = value.downcast_ref::<IntegerValue>() {
-            self.lower += other.value;
-            self.upper += other.value;
+            self.lower = self.lower.map(|lower| lower + other.value);
+            self.upper = self.upper.map(|upper| upper + other.value);
             Ok(self)
         } else {
             CashError::InvalidOperation("add".to_owned(), "range ".to_owned() + typename).boxed()
@@ -80,8 +207,8 @@ impl Value for RangeValue {
     fn subtract(mut self: Box<Self>, value: &Box<dyn Value>) -> ValueResult {
         let typename = value.get_type_name();
         if let Some(other) = value.downcast_ref::<IntegerValue>() {
-            self.lower -= other.value;
-            self.upper -= other.value;
+            self.lower = self.lower.map(|lower| lower - other.value);
+            self.upper = self.upper.map(|upper| upper - other.value);
             Ok(self)
         } else {
             CashError::InvalidOperation("subtract".to_owned(), "range ".to_owned() + typename)
@@ -91,9 +218,9 @@ impl Value for RangeValue {
     fn contains(&self, value: &Box<dyn Value>) -> ValueResult {
         let typename = value.get_type_name();
         if let Some(other) = value.downcast_ref::<IntegerValue>() {
-            BooleanValue::boxed(self.lower <= other.value && self.upper > other.value)
+            BooleanValue::boxed(self.contains_number(other.value as f64, true))
         } else if let Some(other) = value.downcast_ref::<FloatValue>() {
-            BooleanValue::boxed(self.lower as f64 <= other.value && self.upper as f64 > other.value)
+            BooleanValue::boxed(self.contains_number(other.value, false))
         } else {
             CashError::InvalidOperation("contains".to_owned(), "range ".to_owned() + typename)
                 .boxed()
@@ -102,7 +229,12 @@ impl Value for RangeValue {
     fn eq(&self, value: &Box<dyn Value>) -> ValueResult {
         let typename = value.get_type_name();
         if let Some(other) = value.downcast_ref::<RangeValue>() {
-            BooleanValue::boxed(self.lower == other.lower && self.upper == other.upper)
+            BooleanValue::boxed(
+                self.lower == other.lower
+                    && self.upper == other.upper
+                    && self.inclusive == other.inclusive
+                    && self.step == other.step,
+            )
         } else {
             CashError::InvalidOperation("equality".to_owned(), "range ".to_owned() + typename)
                 .boxed()
@@ -112,11 +244,21 @@ impl Value for RangeValue {
         self.eq(value)?.not()
     }
 
+    /// Produces the integers on demand, the range is never allocated
+    fn iter(self: Box<Self>) -> Result<IteratorValue, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(IteratorValue::new(
+            self.indices(None).map(IntegerValue::boxed),
+        ))
+    }
+
     fn vec(
         self: Box<Self>,
     ) -> Result<Vec<Box<dyn Value>>, Box<dyn std::error::Error + Sync + Send>> {
+        if self.upper.is_none() {
+            return CashError::InvalidOperation("vec".to_owned(), "open range".to_owned()).boxed();
+        }
         let mut vec: Vec<Box<dyn Value>> = Vec::new();
-        for i in self.lower..self.upper {
+        for i in self.indices(None) {
             vec.push(Box::new(IntegerValue { value: i }));
         }
         Ok(vec)
@@ -125,6 +267,19 @@ impl Value for RangeValue {
 
 impl std::fmt::Display for RangeValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}..{}", self.lower, self.upper)
+        if let Some(lower) = self.lower {
+            write!(f, "{}", lower)?;
+        }
+        write!(f, "..")?;
+        if self.inclusive {
+            write!(f, "=")?;
+        }
+        if let Some(upper) = self.upper {
+            write!(f, "{}", upper)?;
+        }
+        if self.step != 1 {
+            write!(f, " step {}", self.step)?;
+        }
+        Ok(())
     }
 }
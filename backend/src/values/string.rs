@@ -35,19 +35,13 @@ impl Value for StringValue {
                 StringValue::boxed(self.value.chars().nth(index).unwrap().to_string())
             }
         } else if let Some(other) = index.downcast_ref::<RangeValue>() {
-            if other.lower < 0 {
-                CashError::IndexOutOfBounds(other.lower, self.get_type_name().to_owned()).boxed()
-            } else if other.upper > self.value.len() as i64 {
-                CashError::IndexOutOfBounds(other.upper, self.get_type_name().to_owned()).boxed()
-            } else {
-                StringValue::boxed(
-                    self.value
-                        .chars()
-                        .skip(other.lower as usize)
-                        .take((other.upper - other.lower) as usize)
-                        .collect(),
-                )
-            }
+            let chars: Vec<char> = self.value.chars().collect();
+            StringValue::boxed(
+                other
+                    .indices(Some(chars.len() as i64))
+                    .map(|i| chars[i as usize])
+                    .collect(),
+            )
         } else {
             CashError::InvalidOperation("index".to_owned(), "string ".to_owned() + typename).boxed()
         }
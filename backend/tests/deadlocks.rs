@@ -0,0 +1,77 @@
+//! Runs in its own process, the interval of the deadlock detector is set for the whole process
+//! Deadlocked threads can never be woken up again, so the deadlock happens in a child process
+
+use backend::{deadlock, values, CashError, Runtime};
+use parking_lot::Mutex;
+use std::process::Command;
+use std::sync::{Arc, Barrier};
+use std::time::Duration;
+
+/// Set for the child process, which runs into the deadlock
+const CHILD: &str = "CASH_DEADLOCK_CHILD";
+
+#[test]
+fn deadlocks() {
+    if std::env::var_os(CHILD).is_some() {
+        deadlock_in_child();
+        // ends the deadlocked threads as well
+        std::process::exit(0);
+    }
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["deadlocks", "--exact", "--nocapture"])
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn deadlock_in_child() {
+    deadlock::set_interval(Duration::from_millis(20));
+    // awaits of jobs, which are not part of the deadlock, keep running
+    let bystander = std::thread::spawn(|| {
+        let mut runtime = Runtime::builder()
+            .deadlock_handler(|_| {})
+            .fail_awaits_on_deadlock(true)
+            .build();
+        runtime
+            .interpret("task = async $ sleep \"1\"\nawait task\n\"done\"".to_owned())
+            .map(|value| value.to_string())
+            .map_err(|err| err.to_string())
+    });
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let received = reports.clone();
+    let mut runtime = Runtime::builder()
+        .deadlock_handler(move |report| received.lock().push(report.clone()))
+        .fail_awaits_on_deadlock(true)
+        .build();
+    // locks two mutexes in the opposite order of another thread
+    let locks = Arc::new((Mutex::new(()), Mutex::new(())));
+    let other = locks.clone();
+    let barrier = Arc::new(Barrier::new(2));
+    let other_barrier = barrier.clone();
+    std::thread::spawn(move || {
+        let _second = other.1.lock();
+        other_barrier.wait();
+        let _first = other.0.lock();
+    });
+    runtime.register_function("hang", move |_, _| {
+        let _first = locks.0.lock();
+        barrier.wait();
+        let _second = locks.1.lock();
+        values::NoneValue::boxed()
+    });
+    let err = runtime
+        .interpret("task = async hang()\nawait task".to_owned())
+        .unwrap_err();
+    assert!(matches!(CashError::find(&*err), Some(CashError::Deadlock)));
+    assert_eq!(bystander.join().unwrap(), Ok("done".to_owned()));
+
+    let reports = reports.lock();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].deadlocks[0].len(), 2);
+}
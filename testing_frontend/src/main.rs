@@ -4,6 +4,10 @@ use rustyline::Editor;
 
 fn main() {
     let mut rt = Runtime::new();
+    // while a line is being edited, rustyline receives Ctrl-C itself
+    // otherwise SIGINT only cancels the running statement
+    let interrupt = rt.interrupt_handle();
+    ctrlc::set_handler(move || interrupt.interrupt()).expect("Cannot set the SIGINT handler");
     // `()` can be used when no completer is required
     let mut rl = Editor::<()>::new();
     /*if rl.load_history("history.txt").is_err() {
@@ -19,22 +23,27 @@ fn main() {
         let path = std::fs::canonicalize(std::path::Path::new(&args[1]))
             .expect("Cannot canonicalize path");
         let contents = std::fs::read_to_string(&path).expect("Errored while reading file!");
-        let cwd = std::env::current_dir().expect("Cannot find current working directory");
-        assert!(
-            std::env::set_current_dir(path.parent().expect("Cannot find parent of file")).is_ok()
-        );
-        match rt.interpret(contents) {
-            Ok(tree_result) => {
-                if tree_result.get_type_name() == "none" {
-                } else {
-                    println!("{}", tree_result);
+        let cwd = rt.cwd();
+        rt.set_cwd(path.parent().expect("Cannot find parent of file"))
+            .expect("Cannot change into the directory of the file");
+        match Runtime::compile_source(&contents, &path.display().to_string()) {
+            Ok(program) => match rt.execute(&program) {
+                Ok(tree_result) => {
+                    if tree_result.get_type_name() == "none" {
+                    } else {
+                        println!("{}", tree_result);
+                    }
                 }
-            }
-            Err(err) => {
-                println!("{}", err);
+                Err(err) => {
+                    rt.report_error(&err);
+                }
+            },
+            Err(diagnostics) => {
+                rt.report_error(&diagnostics);
             }
         }
-        assert!(std::env::set_current_dir(&cwd).is_ok());
+        rt.set_cwd(&cwd)
+            .expect("Cannot change back into the working directory");
     }
 
     loop {
@@ -50,13 +59,12 @@ fn main() {
                         }
                     }
                     Err(err) => {
-                        println!("{}", err);
+                        rt.report_error(&err);
                     }
                 }
             }
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
-                break;
             }
             Err(ReadlineError::Eof) => {
                 println!("CTRL-D");